@@ -0,0 +1,181 @@
+use anyhow::{Context, Ok, bail};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use tokio::net::{UdpSocket, lookup_host};
+use tokio::time::timeout;
+
+/// Porta UDP padrão do serviço SQL Server Browser.
+pub const SQL_BROWSER_PORT: u16 = 1434;
+
+/// Tempo máximo de espera pela resposta do SQL Server Browser.
+const BROWSER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// CLNT_UCAST_INST: solicita os dados de uma única instância.
+const CLNT_UCAST_INST: u8 = 0x04;
+
+/// SVR_RESP: cabeçalho da resposta enviada pelo browser.
+const SVR_RESP: u8 = 0x05;
+
+pub async fn resolve_instance_port(
+    host: &str,
+    instance: &str,
+    browser_port: u16,
+) -> anyhow::Result<u16> {
+    //! Consulta o SQL Server Browser (protocolo SSRP) para descobrir
+    //! a porta TCP de uma instância nomeada.
+    //! Envia uma requisição `CLNT_UCAST_INST` para `host:browser_port`
+    //! e lê o campo `tcp` da resposta.
+
+    let address = lookup_host((host, browser_port))
+        .await
+        .ok()
+        .and_then(|mut addresses| addresses.next())
+        .with_context(|| format!("Falha ao resolver o endereço do servidor {}", host))?;
+
+    // o socket local precisa ser da mesma família (IPv4 ou IPv6) do servidor
+    let local: SocketAddr = match address {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+
+    let socket = UdpSocket::bind(local).await?;
+    socket
+        .connect(address)
+        .await
+        .with_context(|| format!("Falha ao contatar o SQL Server Browser em {}", host))?;
+
+    let mut request = Vec::with_capacity(instance.len() + 2);
+    request.push(CLNT_UCAST_INST);
+    request.extend_from_slice(instance.as_bytes());
+    request.push(0);
+    socket.send(&request).await?;
+
+    let mut buffer = vec![0u8; 4096];
+    let size = timeout(BROWSER_TIMEOUT, socket.recv(&mut buffer))
+        .await
+        .with_context(|| {
+            format!(
+                "SQL Server Browser em {}:{} não respondeu",
+                host, browser_port
+            )
+        })??;
+
+    parse_browser_response(&buffer[..size], instance)
+}
+
+fn parse_browser_response(response: &[u8], instance: &str) -> anyhow::Result<u16> {
+    //! Interpreta a resposta `SVR_RESP` do browser.
+    //! O corpo é uma lista `chave;valor;` terminada por `;;`.
+
+    if response.len() < 3 || response[0] != SVR_RESP {
+        bail!("Resposta inválida do SQL Server Browser");
+    }
+
+    let declared = u16::from_le_bytes([response[1], response[2]]) as usize;
+    let body = &response[3..];
+    let body = &body[..declared.min(body.len())];
+    let text = String::from_utf8_lossy(body);
+
+    // cada instância termina com `;;`
+    for entry in text.split(";;").filter(|e| !e.is_empty()) {
+        let fields: Vec<&str> = entry.split(';').collect();
+        let mut name: Option<&str> = None;
+        let mut port: Option<&str> = None;
+
+        for pair in fields.chunks(2) {
+            if let [key, value] = pair {
+                match key.to_ascii_lowercase().as_str() {
+                    "instancename" => name = Some(value),
+                    "tcp" => port = Some(value),
+                    _ => {}
+                }
+            }
+        }
+
+        if name.is_some_and(|n| n.eq_ignore_ascii_case(instance)) {
+            let port = port.with_context(|| {
+                format!(
+                    "Instância {} não possui o protocolo TCP habilitado",
+                    instance
+                )
+            })?;
+            return Ok(port.parse()?);
+        }
+    }
+
+    bail!(
+        "Instância {} não encontrada no SQL Server Browser",
+        instance
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INSTANCES: &str = "ServerName;HOST;InstanceName;SQLEXPRESS;IsClustered;No;\
+        Version;15.0.2000.5;tcp;14330;np;\\\\HOST\\pipe\\MSSQL$SQLEXPRESS\\sql\\query;;\
+        ServerName;HOST;InstanceName;SEMTCP;IsClustered;No;\
+        Version;15.0.2000.5;np;\\\\HOST\\pipe\\MSSQL$SEMTCP\\sql\\query;;";
+
+    async fn browser_stand_in() -> u16 {
+        browser_stand_in_at("127.0.0.1:0").await
+    }
+
+    async fn browser_stand_in_at(address: &str) -> u16 {
+        //! Responde a uma requisição `CLNT_UCAST_INST` com as instâncias de teste.
+
+        let socket = UdpSocket::bind(address).await.unwrap();
+        let port = socket.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 256];
+            let (size, client) = socket.recv_from(&mut buffer).await.unwrap();
+            assert_eq!(buffer[0], CLNT_UCAST_INST);
+            assert_eq!(buffer[size - 1], 0);
+
+            let mut response = vec![SVR_RESP];
+            response.extend((INSTANCES.len() as u16).to_le_bytes());
+            response.extend(INSTANCES.as_bytes());
+            socket.send_to(&response, client).await.unwrap();
+        });
+
+        port
+    }
+
+    #[tokio::test]
+    async fn resolves_tcp_port() {
+        let port = browser_stand_in().await;
+
+        let tcp = resolve_instance_port("127.0.0.1", "sqlexpress", port).await;
+        assert_eq!(tcp.unwrap(), 14330);
+    }
+
+    #[tokio::test]
+    async fn resolves_tcp_port_over_ipv6() {
+        let port = browser_stand_in_at("[::1]:0").await;
+
+        let tcp = resolve_instance_port("::1", "SQLEXPRESS", port).await;
+        assert_eq!(tcp.unwrap(), 14330);
+    }
+
+    #[tokio::test]
+    async fn unknown_instance_is_an_error() {
+        let port = browser_stand_in().await;
+
+        let error = resolve_instance_port("127.0.0.1", "OUTRA", port)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("não encontrada"));
+    }
+
+    #[tokio::test]
+    async fn instance_without_tcp_is_an_error() {
+        let port = browser_stand_in().await;
+
+        let error = resolve_instance_port("127.0.0.1", "SEMTCP", port)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("TCP"));
+    }
+}
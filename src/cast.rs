@@ -0,0 +1,253 @@
+use crate::{MSchema, parse_precision_scale, read_object_file};
+use anyhow::{Context, Ok, bail};
+use std::path::Path;
+
+/// Conversão de tipo de uma coluna, informada como `coluna=tipo`,
+/// por exemplo `codigo=bigint`, `emissao=date` ou `valor=float`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnCast {
+    pub column: String,
+    pub data_type: String,
+    pub numeric_precision: Option<u8>,
+    pub numeric_scale: Option<u8>,
+    pub datetime_precision: Option<u8>,
+}
+
+impl ColumnCast {
+    pub fn parse(cast: &str) -> anyhow::Result<Self> {
+        //! Interpreta `coluna=tipo` de `--cast`.
+
+        let (column, sql_type) = cast
+            .rsplit_once('=')
+            .with_context(|| format!("Conversão {} deve estar no formato coluna=tipo", cast))?;
+
+        Self::new(column, sql_type)
+    }
+
+    pub fn new(column: &str, sql_type: &str) -> anyhow::Result<Self> {
+        //! Valida o tipo de destino, os nomes aceitos são os do SQL Server
+        //! e os apelidos `double`, `string`, `timestamp` e `uuid`.
+        //! `hierarchyid`, `geography` e `geometry` decodificam colunas
+        //! convertidas para `varbinary` na consulta.
+
+        let column = column.trim();
+        if column.is_empty() {
+            bail!("Conversão sem o nome da coluna: ={}", sql_type);
+        }
+
+        let sql_type = sql_type.trim().to_lowercase();
+        let (type_name, args) = match sql_type.split_once('(') {
+            Some((name, args)) => (name.trim(), Some(args.trim_end_matches(')').trim())),
+            None => (sql_type.as_str(), None),
+        };
+
+        let mut cast = Self {
+            column: column.to_string(),
+            data_type: String::new(),
+            numeric_precision: None,
+            numeric_scale: None,
+            datetime_precision: Some(0),
+        };
+
+        cast.data_type = match type_name {
+            "tinyint" | "smallint" | "int" | "bigint" | "bit" | "real" | "float" | "date" => {
+                type_name.to_string()
+            }
+            "double" => "float".to_string(),
+            "decimal" | "numeric" => {
+                let (precision, scale) = parse_precision_scale(args)?;
+                cast.numeric_precision = Some(precision);
+                cast.numeric_scale = Some(scale);
+                "decimal".to_string()
+            }
+            "datetime" => {
+                cast.datetime_precision = Some(3);
+                "datetime".to_string()
+            }
+            "datetime2" | "timestamp" | "time" => {
+                cast.datetime_precision = Some(parse_time_precision(args)?);
+                match type_name {
+                    "time" => "time".to_string(),
+                    _ => "datetime2".to_string(),
+                }
+            }
+            "nvarchar" | "varchar" | "string" | "str" => "nvarchar".to_string(),
+            "uniqueidentifier" | "uuid" => "uniqueidentifier".to_string(),
+            // valores varbinary com a serialização do SQL Server
+            "hierarchyid" | "geography" | "geometry" => type_name.to_string(),
+            _ => bail!("Tipo {} não suportado em --cast", sql_type),
+        };
+
+        Ok(cast)
+    }
+
+    fn matches(&self, column_name: Option<&str>, parquet_name: &str) -> bool {
+        //! Compara com o nome original e com o nome gravado no parquet,
+        //! sem diferenciar maiúsculas de minúsculas.
+
+        column_name.is_some_and(|name| name.eq_ignore_ascii_case(&self.column))
+            || parquet_name.eq_ignore_ascii_case(&self.column)
+    }
+}
+
+fn parse_time_precision(args: Option<&str>) -> anyhow::Result<u8> {
+    //! Precisão das frações de segundo, de 0 a 7, o padrão é 7.
+
+    let Some(args) = args else {
+        return Ok(7);
+    };
+
+    let precision: u8 = args.parse()?;
+    if precision > 7 {
+        bail!("Precisão de data e hora {} inválida", precision);
+    }
+
+    Ok(precision)
+}
+
+pub fn read_cast_file(path: &Path) -> anyhow::Result<Vec<ColumnCast>> {
+    //! Lê as conversões de um arquivo JSON ou TOML,
+    //! um objeto com `coluna = "tipo"` no nível principal.
+
+    read_object_file(path, "conversões")?
+        .into_iter()
+        .map(|(column, sql_type)| {
+            let sql_type = sql_type
+                .as_str()
+                .with_context(|| format!("Tipo da coluna {} deve ser um texto", column))?;
+            ColumnCast::new(&column, sql_type)
+                .with_context(|| format!("Conversão da coluna {} do arquivo", column))
+        })
+        .collect()
+}
+
+pub fn apply_casts<'a>(
+    schema_sql: &mut [MSchema],
+    parquet_names: &[String],
+    casts: &'a [ColumnCast],
+) -> Vec<&'a str> {
+    //! Substitui o tipo das colunas que possuem conversão,
+    //! a conversão tem prioridade sobre o tipo informado pelo servidor.
+    //! Retorna as colunas das conversões que não foram encontradas.
+
+    let mut unmatched: Vec<&str> = Vec::new();
+
+    for cast in casts {
+        let mut found = false;
+
+        for (mssql, parquet_name) in schema_sql.iter_mut().zip(parquet_names) {
+            if !cast.matches(mssql.column_name.as_deref(), parquet_name) {
+                continue;
+            }
+
+            mssql.data_type = Some(cast.data_type.clone());
+            mssql.numeric_precision = cast.numeric_precision;
+            mssql.numeric_scale = cast.numeric_scale;
+            mssql.datetime_precision = cast.datetime_precision;
+            found = true;
+        }
+
+        if !found {
+            unmatched.push(&cast.column);
+        }
+    }
+
+    unmatched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, data_type: &str) -> MSchema {
+        MSchema {
+            column_name: Some(name.to_string()),
+            data_type: Some(data_type.to_string()),
+            is_nullable: Some("YES".to_string()),
+            numeric_precision: None,
+            numeric_scale: None,
+            datetime_precision: None,
+        }
+    }
+
+    #[test]
+    fn parse_types_and_aliases() {
+        let cast = ColumnCast::parse("valor = decimal(18, 2)").unwrap();
+        assert_eq!(cast.column, "valor");
+        assert_eq!(cast.data_type, "decimal");
+        assert_eq!(
+            (cast.numeric_precision, cast.numeric_scale),
+            (Some(18), Some(2))
+        );
+
+        let cast = ColumnCast::parse("criado=TIMESTAMP(3)").unwrap();
+        assert_eq!(cast.data_type, "datetime2");
+        assert_eq!(cast.datetime_precision, Some(3));
+
+        assert_eq!(ColumnCast::parse("a=double").unwrap().data_type, "float");
+        assert_eq!(
+            ColumnCast::parse("a=uuid").unwrap().data_type,
+            "uniqueidentifier"
+        );
+        // o último `=` separa o tipo, nomes de coluna podem conter `=`
+        assert_eq!(ColumnCast::parse("a=b=int").unwrap().column, "a=b");
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(ColumnCast::parse("valor").is_err());
+        assert!(ColumnCast::parse("=int").is_err());
+        assert!(ColumnCast::parse("valor=xml").is_err());
+        assert!(ColumnCast::parse("valor=decimal(40,2)").is_err());
+        assert!(ColumnCast::parse("hora=time(8)").is_err());
+    }
+
+    #[test]
+    fn casts_match_original_or_parquet_name() {
+        let mut schema = vec![column("CodigoCliente", "nvarchar"), column("", "int")];
+        let names = vec!["codigo_cliente".to_string(), "col_2".to_string()];
+        let casts = vec![
+            ColumnCast::parse("codigocliente=bigint").unwrap(),
+            ColumnCast::parse("COL_2=float").unwrap(),
+            ColumnCast::parse("inexistente=int").unwrap(),
+        ];
+
+        let unmatched = apply_casts(&mut schema, &names, &casts);
+
+        assert_eq!(schema[0].data_type.as_deref(), Some("bigint"));
+        assert_eq!(schema[1].data_type.as_deref(), Some("float"));
+        assert_eq!(unmatched, vec!["inexistente"]);
+    }
+
+    #[test]
+    fn command_line_casts_override_the_file() {
+        let path =
+            std::env::temp_dir().join(format!("rustmssql_casts_{}.toml", std::process::id()));
+        std::fs::write(&path, "valor = \"float\"\nemissao = \"date\"\n").unwrap();
+
+        // como no main, as conversões de --cast vêm depois das do arquivo
+        let mut casts = read_cast_file(&path).unwrap();
+        casts.push(ColumnCast::parse("valor=decimal(18,2)").unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        let mut schema = vec![column("valor", "money"), column("emissao", "datetime")];
+        let names = vec!["valor".to_string(), "emissao".to_string()];
+        assert!(apply_casts(&mut schema, &names, &casts).is_empty());
+
+        assert_eq!(schema[0].data_type.as_deref(), Some("decimal"));
+        assert_eq!(schema[0].numeric_scale, Some(2));
+        assert_eq!(schema[1].data_type.as_deref(), Some("date"));
+    }
+
+    #[test]
+    fn cast_file_values_must_be_text() {
+        let path =
+            std::env::temp_dir().join(format!("rustmssql_casts_{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"valor": 1}"#).unwrap();
+
+        let error = read_cast_file(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(error.to_string().contains("deve ser um texto"));
+    }
+}
@@ -0,0 +1,224 @@
+use crate::{ApplicationIntent, ConnectionOptions, EncryptMode};
+use anyhow::{Context, Ok, bail};
+use std::time::Duration;
+
+pub fn parse_connection_string(connection_string: &str) -> anyhow::Result<ConnectionOptions> {
+    //! Interpreta uma connection string no formato ADO.NET,
+    //! `Server=...;Database=...;User Id=...;Password=...`.
+    //! As chaves não diferenciam maiúsculas de minúsculas e os valores
+    //! podem estar entre aspas simples, aspas duplas ou chaves.
+
+    let mut options = ConnectionOptions::default();
+    let mut server: Option<String> = None;
+    let mut integrated = false;
+
+    for (key, value) in split_pairs(connection_string)? {
+        match key.to_lowercase().as_str() {
+            "server" | "data source" | "address" | "addr" | "network address" => {
+                server = Some(value)
+            }
+            "database" | "initial catalog" => options.database = Some(value),
+            "user id" | "uid" | "user" | "username" => options.user = Some(value),
+            "password" | "pwd" => options.password = Some(value),
+            "application name" | "applicationname" | "app" => {
+                options.application_name = Some(value)
+            }
+            "applicationintent" | "application intent" => {
+                options.application_intent = parse_application_intent(&value)?
+            }
+            "connect timeout" | "connection timeout" | "timeout" => {
+                let timeout = Duration::from_secs(parse_number(&key, &value)?);
+                options.connect_timeout = timeout;
+                options.login_timeout = timeout;
+            }
+            "connectretrycount" | "connect retry count" => {
+                options.retry.max_retries = parse_number(&key, &value)? as u32
+            }
+            "connectretryinterval" | "connect retry interval" => {
+                options.retry.initial_delay = Duration::from_secs(parse_number(&key, &value)?)
+            }
+            "encrypt" => options.encrypt = parse_encrypt(&value)?,
+            "trustservercertificate" | "trust server certificate" => {
+                options.trust_server_certificate = parse_bool(&key, &value)?
+            }
+            "servercertificate" | "trustservercertificateca" => {
+                options.ca_certificate = Some(value.into())
+            }
+            "hostnameincertificate" | "host name in certificate" => {
+                options.host_name_in_certificate = Some(value)
+            }
+            "integrated security" | "integratedsecurity" | "trusted_connection" => {
+                integrated = value.eq_ignore_ascii_case("sspi") || parse_bool(&key, &value)?
+            }
+            _ => eprintln!("Aviso: opção '{}' da connection string ignorada", key),
+        }
+    }
+
+    match server {
+        Some(server) if !server.trim().is_empty() => options.server = server,
+        _ => bail!("Connection string sem a chave Server"),
+    }
+
+    // autenticacao integrada ignora usuario e senha
+    if integrated {
+        options.user = None;
+        options.password = None;
+    }
+
+    Ok(options)
+}
+
+fn split_pairs(connection_string: &str) -> anyhow::Result<Vec<(String, String)>> {
+    //! Separa a connection string em pares `chave=valor`.
+    //! Respeita `;` dentro de valores entre aspas ou chaves.
+
+    let mut pairs = Vec::new();
+    let mut chars = connection_string.chars().peekable();
+
+    loop {
+        // chave
+        let mut key = String::new();
+        let mut has_value = false;
+        for c in chars.by_ref() {
+            if c == '=' {
+                has_value = true;
+                break;
+            }
+            key.push(c);
+        }
+
+        let key = key.trim().trim_start_matches(';').trim().to_string();
+        if key.is_empty() {
+            break;
+        }
+        if !has_value {
+            bail!("Chave '{}' sem valor na connection string", key);
+        }
+
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        // valor
+        let mut value = String::new();
+        match chars.peek().copied() {
+            Some(quote @ ('"' | '\'' | '{')) => {
+                let close = if quote == '{' { '}' } else { quote };
+                chars.next();
+                loop {
+                    match chars.next() {
+                        // aspas duplicadas representam a propria aspa
+                        Some(c) if c == close && chars.peek() == Some(&close) => {
+                            chars.next();
+                            value.push(c);
+                        }
+                        Some(c) if c == close => break,
+                        Some(c) => value.push(c),
+                        None => bail!("Valor da chave '{}' sem fechamento", key),
+                    }
+                }
+                while chars.next_if(|c| *c != ';').is_some() {}
+            }
+            _ => {
+                while let Some(c) = chars.next_if(|c| *c != ';') {
+                    value.push(c);
+                }
+                value = value.trim().to_string();
+            }
+        }
+        chars.next();
+
+        pairs.push((key, value));
+    }
+
+    Ok(pairs)
+}
+
+fn parse_bool(key: &str, value: &str) -> anyhow::Result<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "yes" => Ok(true),
+        "false" | "no" => Ok(false),
+        _ => bail!("Valor inválido para '{}': {}", key, value),
+    }
+}
+
+fn parse_number(key: &str, value: &str) -> anyhow::Result<u64> {
+    value
+        .trim()
+        .parse()
+        .with_context(|| format!("Valor inválido para '{}': {}", key, value))
+}
+
+fn parse_encrypt(value: &str) -> anyhow::Result<EncryptMode> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "yes" | "mandatory" => Ok(EncryptMode::Required),
+        "false" | "no" | "optional" => Ok(EncryptMode::Off),
+        // o TLS antes do login do TDS 8 não é suportado pelo tiberius
+        "strict" => bail!("'Encrypt=Strict' (TDS 8) não é suportado pelo driver"),
+        _ => bail!("Valor inválido para 'Encrypt': {}", value),
+    }
+}
+
+fn parse_application_intent(value: &str) -> anyhow::Result<ApplicationIntent> {
+    match value.trim().to_lowercase().as_str() {
+        "readwrite" => Ok(ApplicationIntent::ReadWrite),
+        "readonly" => Ok(ApplicationIntent::ReadOnly),
+        _ => bail!("Valor inválido para 'ApplicationIntent': {}", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(connection_string: &str) -> Vec<(String, String)> {
+        split_pairs(connection_string).unwrap()
+    }
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn plain_values_are_trimmed() {
+        assert_eq!(
+            pairs(" Server = tcp:host,1433 ; Database=vendas;;"),
+            vec![pair("Server", "tcp:host,1433"), pair("Database", "vendas")]
+        );
+    }
+
+    #[test]
+    fn quoted_values_keep_separators() {
+        assert_eq!(
+            pairs(r#"Password="a;b=c"; User Id='x;y'; Application Name={app;1}"#),
+            vec![
+                pair("Password", "a;b=c"),
+                pair("User Id", "x;y"),
+                pair("Application Name", "app;1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn doubled_quotes_are_escapes() {
+        assert_eq!(
+            pairs(r#"Password="a""b";User Id='o''neil';Application Name={x}}y}"#),
+            vec![
+                pair("Password", r#"a"b"#),
+                pair("User Id", "o'neil"),
+                pair("Application Name", "x}y"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_quote_and_missing_value_are_errors() {
+        assert!(split_pairs(r#"Password="abc;Server=host"#).is_err());
+        assert!(split_pairs("Server=host;Database").is_err());
+    }
+
+    #[test]
+    fn encrypt_values() {
+        assert_eq!(parse_encrypt("True").unwrap(), EncryptMode::Required);
+        assert_eq!(parse_encrypt("false").unwrap(), EncryptMode::Off);
+        assert!(parse_encrypt("Strict").is_err());
+    }
+}
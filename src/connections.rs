@@ -1,12 +1,17 @@
-use anyhow::Ok;
-use tiberius::{AuthMethod, Client, Config};
-use tiberius::{Query, QueryItem, QueryStream};
+use crate::browser::{SQL_BROWSER_PORT, resolve_instance_port};
+use crate::retry::{RetryPolicy, is_transient};
+use anyhow::{Context, Ok, anyhow, bail};
+use std::path::PathBuf;
+use std::time::Duration;
+use tiberius::{AuthMethod, Client, Config, EncryptionLevel};
+use tiberius::{Column, ColumnType, Query, QueryItem, QueryStream};
 use tokio::net::TcpStream;
+use tokio::time::timeout;
 use tokio_stream::StreamExt;
 use tokio_util::compat::Compat;
 use tokio_util::compat::TokioAsyncWriteCompatExt;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct MSchema {
     pub column_name: Option<String>,
     pub data_type: Option<String>,
@@ -16,64 +21,824 @@ pub struct MSchema {
     pub datetime_precision: Option<u8>,
 }
 
+impl MSchema {
+    pub fn base_type(&self) -> String {
+        //! Tipo de dado sem o tamanho ou a precisão, `decimal(18,2)` vira `decimal`.
+
+        let data_type = self.data_type.as_deref().unwrap_or_default();
+
+        data_type
+            .split('(')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase()
+    }
+
+    pub fn binary_length(&self) -> Option<usize> {
+        //! Tamanho de `binary(n)`, `None` quando o tipo não informa o tamanho.
+
+        let data_type = self.data_type.as_deref().unwrap_or_default();
+        let (_, length) = data_type.split_once('(')?;
+
+        length.trim_end_matches(')').trim().parse().ok()
+    }
+
+    pub fn decimal_precision(&self) -> (u8, u8) {
+        //! Precisão e escala do decimal gravado no parquet.
+        //! `money` e `smallmoney` são inteiros escalados por 10.000 no TDS
+        //! e viram `decimal(19,4)` e `decimal(10,4)`.
+
+        match self.base_type().as_str() {
+            "money" => (19, 4),
+            "smallmoney" => (10, 4),
+            _ => (
+                self.numeric_precision.unwrap_or(0),
+                self.numeric_scale.unwrap_or(0),
+            ),
+        }
+    }
+}
+
+/// Endereço do servidor informado como `host`, `host,porta`,
+/// `host:porta` ou `host\instancia`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerAddress {
+    pub host: String,
+    pub port: Option<u16>,
+    pub instance: Option<String>,
+}
+
+impl ServerAddress {
+    pub fn parse(server: &str) -> anyhow::Result<Self> {
+        //! Separa o host, a porta e a instância nomeada.
+        //! Quando a porta é informada ela tem prioridade sobre a instância.
+
+        let server = server.trim();
+        let server = server.strip_prefix("tcp:").unwrap_or(server);
+
+        let (rest, port) = match server.rsplit_once(',') {
+            Some((rest, port)) => (rest, Some(port)),
+            // `[ipv6]:porta`
+            None if server.starts_with('[') => match server.rsplit_once("]:") {
+                Some((rest, port)) => (rest, Some(port)),
+                None => (server, None),
+            },
+            // `host:porta`, ignorando enderecos IPv6 sem colchetes
+            None if server.matches(':').count() == 1 => server
+                .split_once(':')
+                .map(|(rest, port)| (rest, Some(port)))
+                .unwrap(),
+            None => (server, None),
+        };
+
+        let port = port
+            .map(|p| {
+                p.trim()
+                    .parse::<u16>()
+                    .with_context(|| format!("Porta inválida: {}", p))
+            })
+            .transpose()?;
+
+        let (host, instance) = match rest.split_once('\\') {
+            Some((host, instance)) => (host, Some(instance.trim().to_string())),
+            None => (rest, None),
+        };
+
+        let host = host.trim().trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            anyhow::bail!("Nome do servidor não informado");
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            instance: instance.filter(|i| !i.is_empty()),
+        })
+    }
+
+    pub fn get_host(&self) -> &str {
+        //! Retorna o host para a conexão, `.` equivale a `localhost`.
+
+        if self.host == "." {
+            "localhost"
+        } else {
+            self.host.as_str()
+        }
+    }
+
+    pub async fn resolve_port(&self, browser_port: u16) -> anyhow::Result<u16> {
+        //! Retorna a porta TCP do servidor.
+        //! Instâncias nomeadas sem porta são resolvidas pelo SQL Server Browser.
+
+        match (self.port, self.instance.as_deref()) {
+            (Some(port), _) => Ok(port),
+            (None, Some(instance)) => {
+                resolve_instance_port(self.get_host(), instance, browser_port).await
+            }
+            (None, None) => Ok(1433),
+        }
+    }
+}
+
+/// Nome de um objeto do banco no formato `banco.schema.tabela`,
+/// com partes opcionalmente entre colchetes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectName {
+    pub database: Option<String>,
+    pub schema: Option<String>,
+    pub name: String,
+}
+
+impl ObjectName {
+    pub fn parse(object_name: &str) -> anyhow::Result<Self> {
+        //! Separa as partes do nome pelos pontos fora de colchetes.
+        //! Aceita `tabela`, `schema.tabela` e `banco.schema.tabela`.
+
+        let mut parts: Vec<String> = Vec::new();
+        let mut current = String::new();
+        let mut chars = object_name.trim().chars().peekable();
+        let mut quoted = false;
+
+        while let Some(c) = chars.next() {
+            match c {
+                '[' if !quoted && current.is_empty() => quoted = true,
+                // `]]` representa um colchete dentro do nome
+                ']' if quoted && chars.peek() == Some(&']') => {
+                    chars.next();
+                    current.push(']');
+                }
+                ']' if quoted => quoted = false,
+                '.' if !quoted => parts.push(std::mem::take(&mut current)),
+                c => current.push(c),
+            }
+        }
+
+        if quoted {
+            bail!("Nome {} com colchete sem fechamento", object_name);
+        }
+        parts.push(current);
+
+        if parts.len() > 3 {
+            bail!("Nome de objeto com mais de três partes: {}", object_name);
+        }
+
+        let mut parts = parts.into_iter().rev();
+        let name = parts.next().unwrap_or_default();
+        // `banco..tabela` usa o schema padrão
+        let schema = parts.next().filter(|s| !s.is_empty());
+        let database = parts.next();
+
+        if name.trim().is_empty() || database.as_ref().is_some_and(|d| d.trim().is_empty()) {
+            bail!("Nome de objeto inválido: {}", object_name);
+        }
+
+        Ok(Self {
+            database,
+            schema,
+            name,
+        })
+    }
+
+    pub fn quoted(&self) -> String {
+        //! Nome completo com as partes entre colchetes, `[banco].[schema].[tabela]`.
+
+        match (&self.database, &self.schema) {
+            (Some(database), Some(schema)) => format!(
+                "{}.{}.{}",
+                quote_identifier(database),
+                quote_identifier(schema),
+                quote_identifier(&self.name)
+            ),
+            (Some(database), None) => format!(
+                "{}..{}",
+                quote_identifier(database),
+                quote_identifier(&self.name)
+            ),
+            (None, Some(schema)) => {
+                format!(
+                    "{}.{}",
+                    quote_identifier(schema),
+                    quote_identifier(&self.name)
+                )
+            }
+            (None, None) => quote_identifier(&self.name),
+        }
+    }
+}
+
+pub fn validate_identifier(identifier: &str) -> anyhow::Result<()> {
+    //! Valida um identificador do SQL Server,
+    //! com até 128 caracteres e sem caracteres de controle.
+
+    if identifier.trim().is_empty() {
+        bail!("Identificador vazio");
+    }
+    if identifier.chars().count() > 128 {
+        bail!("Identificador com mais de 128 caracteres: {}", identifier);
+    }
+    if identifier.chars().any(|c| c.is_control()) {
+        bail!("Identificador com caracteres inválidos: {:?}", identifier);
+    }
+
+    Ok(())
+}
+
+pub fn quote_identifier(identifier: &str) -> String {
+    //! Coloca o identificador entre colchetes, escapando `]`.
+
+    format!("[{}]", identifier.replace(']', "]]"))
+}
+
+pub fn select_table_sql(table: &ObjectName, schema: &[MSchema]) -> String {
+    //! Monta o `SELECT` de uma tabela com as colunas na ordem do schema,
+    //! todos os identificadores entre colchetes, convertidas por `select_column_sql`.
+
+    format!(
+        "SELECT {} FROM {}",
+        select_columns_sql(schema),
+        table.quoted()
+    )
+}
+
+pub fn wrap_query_sql(query: &str, schema: &[MSchema]) -> Option<String> {
+    //! Envolve a consulta em um `SELECT` que converte no servidor as colunas
+    //! que o tiberius não decodifica ou entrega como f64, como no `--table`.
+    //! `None` quando nenhuma coluna precisa de conversão.
+    //! Consultas com `ORDER BY` sem `TOP`, CTEs, vários lotes ou colunas sem nome
+    //! não podem ser subconsultas e falham ao serem descritas.
+
+    if !schema.iter().any(|c| needs_conversion(&c.base_type())) {
+        return None;
+    }
+
+    let query = query.trim_end().trim_end_matches(';');
+
+    Some(format!(
+        "SELECT {} FROM (\n{}\n) AS [rustmssql_query]",
+        select_columns_sql(schema),
+        query
+    ))
+}
+
+fn needs_conversion(data_type: &str) -> bool {
+    matches!(
+        data_type,
+        "money" | "smallmoney" | "hierarchyid" | "geography" | "geometry" | "sql_variant"
+    )
+}
+
+fn select_columns_sql(schema: &[MSchema]) -> String {
+    schema
+        .iter()
+        .filter_map(|c| Some((c, quote_identifier(c.column_name.as_deref()?))))
+        .map(|(c, name)| select_column_sql(c, name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn select_column_sql(column: &MSchema, name: String) -> String {
+    //! Colunas `money` e `smallmoney` são lidas como `decimal` para manter os centavos exatos.
+    //! Tipos que o tiberius não decodifica são convertidos no servidor:
+    //! `hierarchyid`, `geography` e `geometry` chegam com a serialização em `varbinary`
+    //! e `sql_variant` como texto, seguido pelo tipo base (ver `variant_type_columns`).
+
+    match column.base_type().as_str() {
+        // o tiberius entrega money como f64, o decimal chega sem arredondamento
+        "money" | "smallmoney" => {
+            let (precision, scale) = column.decimal_precision();
+            format!(
+                "CAST({} AS decimal({},{})) AS {}",
+                name, precision, scale, name
+            )
+        }
+        "hierarchyid" | "geography" | "geometry" => {
+            format!("CAST({} AS varbinary(max)) AS {}", name, name)
+        }
+        "sql_variant" => format!(
+            "{} AS {}, CAST(SQL_VARIANT_PROPERTY({}, 'BaseType') AS nvarchar(128)) AS {}",
+            variant_value_sql(&name),
+            name,
+            name,
+            quote_identifier(&variant_type_name(column))
+        ),
+        _ => name,
+    }
+}
+
+fn variant_value_sql(name: &str) -> String {
+    //! Texto do `sql_variant` pelo tipo base, a conversão padrão
+    //! arredonda `float` e `money` e formata `datetime` como `Jan  1 2024 12:00AM`.
+
+    format!(
+        "CASE CAST(SQL_VARIANT_PROPERTY({name}, 'BaseType') AS nvarchar(128))
+            WHEN 'float' THEN CONVERT(nvarchar(4000), CAST({name} AS float), 3)
+            WHEN 'real' THEN CONVERT(nvarchar(4000), CAST({name} AS real), 3)
+            WHEN 'money' THEN CONVERT(nvarchar(4000), CAST({name} AS money), 2)
+            WHEN 'smallmoney' THEN CONVERT(nvarchar(4000), CAST({name} AS money), 2)
+            WHEN 'datetime' THEN CONVERT(nvarchar(4000), CAST({name} AS datetime2(3)))
+            WHEN 'smalldatetime' THEN CONVERT(nvarchar(4000), CAST({name} AS datetime2(0)))
+            WHEN 'binary' THEN CONVERT(nvarchar(4000), CAST({name} AS varbinary(8000)), 1)
+            WHEN 'varbinary' THEN CONVERT(nvarchar(4000), CAST({name} AS varbinary(8000)), 1)
+            ELSE CONVERT(nvarchar(4000), {name})
+        END",
+        name = name
+    )
+}
+
+fn variant_type_name(column: &MSchema) -> String {
+    format!("{}_type", column.column_name.as_deref().unwrap_or_default())
+}
+
+pub fn variant_type_columns(schema: Vec<MSchema>) -> Vec<MSchema> {
+    //! Acrescenta após cada coluna `sql_variant` a coluna `<coluna>_type`
+    //! com o tipo base do valor, na mesma ordem do `select_table_sql`.
+
+    let mut columns = Vec::with_capacity(schema.len());
+
+    for column in schema {
+        let variant = column.base_type() == "sql_variant";
+        let type_name = variant_type_name(&column);
+        columns.push(column);
+
+        if variant {
+            columns.push(MSchema {
+                column_name: Some(type_name),
+                data_type: Some("nvarchar".to_string()),
+                is_nullable: Some("YES".to_string()),
+                numeric_precision: None,
+                numeric_scale: None,
+                datetime_precision: None,
+            });
+        }
+    }
+
+    columns
+}
+
+pub fn check_driver_types(schema: &[MSchema]) -> anyhow::Result<()> {
+    //! O tiberius interrompe a leitura em colunas `hierarchyid`, `geography`,
+    //! `geometry`, `sql_variant` e outros tipos CLR.
+    //! Usado quando a consulta não pode ser envolvida por `wrap_query_sql`
+    //! e a conversão precisa estar no SQL.
+
+    for column in schema {
+        let name = column.column_name.as_deref().unwrap_or_default();
+        let data_type = column.base_type();
+
+        let hint = match data_type.as_str() {
+            "hierarchyid" => format!(
+                "use {}.ToString() ou CAST({} AS varbinary(max)) com --cast {}=hierarchyid",
+                name, name, name
+            ),
+            "geography" | "geometry" => format!(
+                "use CAST({} AS varbinary(max)) com --cast {}={}",
+                name, name, data_type
+            ),
+            "sql_variant" => format!(
+                "use CONVERT(nvarchar(4000), {}) e SQL_VARIANT_PROPERTY({}, 'BaseType')",
+                name, name
+            ),
+            // tipos CLR do usuário são descritos como `banco.schema.tipo`
+            _ if data_type.contains('.') => format!("use CAST({} AS varbinary(max))", name),
+            _ => continue,
+        };
+
+        bail!(
+            "Coluna {} do tipo {} não é lida pelo driver e a consulta não pode ser \
+             convertida automaticamente, {}, ou exporte com --table",
+            name,
+            data_type,
+            hint
+        );
+    }
+
+    Ok(())
+}
+
+/// Opções de conexão, informadas pela linha de comando
+/// ou por uma connection string.
+#[derive(Clone)]
+pub struct ConnectionOptions {
+    pub server: String,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub database: Option<String>,
+    pub application_name: Option<String>,
+    pub application_intent: ApplicationIntent,
+    pub encrypt: EncryptMode,
+    pub trust_server_certificate: bool,
+    pub ca_certificate: Option<PathBuf>,
+    pub host_name_in_certificate: Option<String>,
+    pub connect_timeout: Duration,
+    pub login_timeout: Duration,
+    pub retry: RetryPolicy,
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        Self {
+            server: String::new(),
+            user: None,
+            password: None,
+            database: None,
+            application_name: None,
+            application_intent: ApplicationIntent::default(),
+            encrypt: EncryptMode::default(),
+            trust_server_certificate: false,
+            ca_certificate: None,
+            host_name_in_certificate: None,
+            connect_timeout: Duration::from_secs(15),
+            login_timeout: Duration::from_secs(30),
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+/// Intenção da conexão, `ReadOnly` direciona para réplicas
+/// secundárias legíveis de um grupo Always On.
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum ApplicationIntent {
+    #[default]
+    #[value(name = "ReadWrite", alias = "readwrite")]
+    ReadWrite,
+    #[value(name = "ReadOnly", alias = "readonly")]
+    ReadOnly,
+}
+
+impl std::fmt::Debug for ConnectionOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // a senha nunca e exibida
+        f.debug_struct("ConnectionOptions")
+            .field("server", &self.server)
+            .field("user", &self.user)
+            .field("password", &self.password.as_ref().map(|_| "***"))
+            .field("database", &self.database)
+            .field("application_name", &self.application_name)
+            .field("application_intent", &self.application_intent)
+            .field("encrypt", &self.encrypt)
+            .field("trust_server_certificate", &self.trust_server_certificate)
+            .field("ca_certificate", &self.ca_certificate)
+            .field("host_name_in_certificate", &self.host_name_in_certificate)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("login_timeout", &self.login_timeout)
+            .field("retry", &self.retry)
+            .finish()
+    }
+}
+
+/// Modo de criptografia da conexão.
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum EncryptMode {
+    /// Criptografa apenas o login
+    Off,
+    /// Criptografa tudo quando o servidor suportar
+    On,
+    /// Criptografa tudo e falha se o servidor não suportar
+    #[default]
+    Required,
+    /// Como `required`, sempre validando o certificado do servidor
+    RequiredVerified,
+}
+
+impl ConnectionOptions {
+    pub fn new(server: &str) -> Self {
+        Self {
+            server: server.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
 pub async fn connect_server(
-    server: &str,
-    user: Option<&str>,
-    password: Option<&str>,
+    options: &ConnectionOptions,
 ) -> anyhow::Result<Client<Compat<TcpStream>>> {
     //! Conecta ao servidor SQL Server.
     //! Retorna um cliente para realizar consultas.
+    //! Aceita `host`, `host,porta`, `host:porta` e `host\instancia`.
+    //! Erros transitórios são repetidos conforme a `RetryPolicy`,
+    //! o erro final lista todas as tentativas.
+
+    let mut failures: Vec<String> = Vec::new();
+    let mut attempt: u32 = 0;
+
+    loop {
+        let error = match connect_once(options).await {
+            Err(error) => error,
+            client => return client,
+        };
+
+        failures.push(format!("tentativa {}: {:#}", attempt + 1, error));
+
+        if !is_transient(&error) || attempt >= options.retry.max_retries {
+            return Err(anyhow!(
+                "Falha ao conectar em {} após {} tentativa(s):\n  {}",
+                options.server,
+                failures.len(),
+                failures.join("\n  ")
+            ));
+        }
+
+        let delay = options.retry.delay(attempt);
+        eprintln!(
+            "Falha transitória na conexão, nova tentativa em {:.1}s ...",
+            delay.as_secs_f64()
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
 
+async fn connect_once(options: &ConnectionOptions) -> anyhow::Result<Client<Compat<TcpStream>>> {
+    //! Realiza uma única tentativa de conexão e login,
+    //! limitadas por `connect_timeout` e `login_timeout`.
+
+    let address = ServerAddress::parse(&options.server)?;
+
+    let port = address.resolve_port(SQL_BROWSER_PORT).await?;
+
+    // o host do config e usado na validacao do certificado
     let mut config: Config = Config::new();
-    config.host(server);
-    config.port(1433);
+    config.host(
+        options
+            .host_name_in_certificate
+            .as_deref()
+            .unwrap_or(address.get_host()),
+    );
+    config.port(port);
 
-    if let (Some(user), Some(password)) = (user, password) {
+    if let Some(database) = &options.database {
+        config.database(database);
+    }
+    if let Some(application_name) = &options.application_name {
+        config.application_name(application_name);
+    }
+    config.readonly(options.application_intent == ApplicationIntent::ReadOnly);
+
+    if let (Some(user), Some(password)) = (&options.user, &options.password) {
         config.authentication(AuthMethod::sql_server(user, password));
     } else {
+        #[cfg(windows)]
         config.authentication(AuthMethod::Integrated);
+        #[cfg(not(windows))]
+        anyhow::bail!(
+            "Autenticação integrada disponível apenas no Windows, informe usuário e senha"
+        );
     }
-    config.trust_cert();
 
-    let tcp_stream: TcpStream = TcpStream::connect(config.get_addr()).await?;
+    configure_tls(&mut config, options)?;
+
+    let tcp_stream: TcpStream = timeout(
+        options.connect_timeout,
+        TcpStream::connect((address.get_host(), port)),
+    )
+    .await
+    .context("Tempo limite de conexão TCP excedido")??;
     tcp_stream.set_nodelay(true)?;
 
-    let client: Client<Compat<TcpStream>> =
-        Client::connect(config, tcp_stream.compat_write()).await?;
+    let client: Client<Compat<TcpStream>> = timeout(
+        options.login_timeout,
+        Client::connect(config, tcp_stream.compat_write()),
+    )
+    .await
+    .context("Tempo limite de login excedido")??;
 
     Ok(client)
 }
 
+fn configure_tls(config: &mut Config, options: &ConnectionOptions) -> anyhow::Result<()> {
+    //! Define a criptografia e a validação do certificado do servidor.
+    //! Por padrão o certificado é validado no repositório do sistema,
+    //! `trust_cert` só é usado com `--trust-server-certificate`.
+
+    if options.trust_server_certificate && options.ca_certificate.is_some() {
+        bail!("--trust-server-certificate e --ca-certificate não podem ser usados juntos");
+    }
+    if options.trust_server_certificate && options.encrypt == EncryptMode::RequiredVerified {
+        bail!("O modo de criptografia required-verified não permite --trust-server-certificate");
+    }
+
+    config.encryption(match options.encrypt {
+        EncryptMode::Off => EncryptionLevel::Off,
+        EncryptMode::On => EncryptionLevel::On,
+        EncryptMode::Required | EncryptMode::RequiredVerified => EncryptionLevel::Required,
+    });
+
+    if let Some(path) = &options.ca_certificate {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        if !matches!(extension.as_str(), "pem" | "crt" | "der") {
+            bail!(
+                "Certificado {} deve ter extensão pem, crt ou der",
+                path.display()
+            );
+        }
+        if !path.is_file() {
+            bail!("Certificado {} não encontrado", path.display());
+        }
+        config.trust_cert_ca(path.to_string_lossy());
+    } else if options.trust_server_certificate {
+        config.trust_cert();
+    }
+
+    Ok(())
+}
+
+pub async fn run_init_statements(
+    client: &mut Client<Compat<TcpStream>>,
+    statements: &[String],
+) -> anyhow::Result<()> {
+    //! Executa as instruções de inicialização da sessão,
+    //! como `SET` e `USE`, antes da descoberta do schema e da consulta.
+    //! Interrompe no primeiro erro.
+
+    for (position, statement) in statements.iter().enumerate() {
+        let context = || {
+            format!(
+                "Falha na instrução de inicialização {}: {}",
+                position + 1,
+                statement.trim()
+            )
+        };
+
+        client
+            .simple_query(statement.as_str())
+            .await
+            .with_context(context)?
+            .into_results()
+            .await
+            .with_context(context)?;
+    }
+
+    Ok(())
+}
+
+pub fn split_batches(sql: &str) -> anyhow::Result<Vec<String>> {
+    //! Separa um script em lotes pelas linhas `GO`, como no `sqlcmd` e no SSMS.
+    //! `GO n` executa o lote n vezes e o `GO` pode ser seguido por um comentário `--`.
+    //! Linhas `GO` dentro de textos ou comentários de bloco fazem parte do lote.
+
+    let mut batches: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut state = ScanState::Code;
+
+    for line in sql.lines() {
+        if state == ScanState::Code {
+            if let Some(count) = go_count(line)? {
+                if !current.trim().is_empty() {
+                    batches.extend(std::iter::repeat_n(current.clone(), count));
+                }
+                current.clear();
+                continue;
+            }
+        }
+
+        state = scan_line(line, state);
+        current.push_str(line);
+        current.push('\n');
+    }
+
+    if !current.trim().is_empty() {
+        batches.push(current);
+    }
+
+    Ok(batches)
+}
+
+/// Onde termina uma linha do script: no código, em um texto ou identificador
+/// delimitado que continua na linha seguinte ou em um comentário de bloco.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScanState {
+    Code,
+    Quoted(char),
+    Comment(u32),
+}
+
+fn scan_line(line: &str, mut state: ScanState) -> ScanState {
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        state = match state {
+            // `''` e `]]` representam o delimitador dentro do texto
+            ScanState::Quoted(close) if c == close => {
+                if chars.next_if_eq(&close).is_some() {
+                    state
+                } else {
+                    ScanState::Code
+                }
+            }
+            ScanState::Quoted(_) => state,
+            ScanState::Comment(depth) => match c {
+                '*' if chars.next_if_eq(&'/').is_some() => match depth {
+                    1 => ScanState::Code,
+                    _ => ScanState::Comment(depth - 1),
+                },
+                '/' if chars.next_if_eq(&'*').is_some() => ScanState::Comment(depth + 1),
+                _ => state,
+            },
+            ScanState::Code => match c {
+                '-' if chars.peek() == Some(&'-') => return state,
+                '/' if chars.next_if_eq(&'*').is_some() => ScanState::Comment(1),
+                '\'' | '"' => ScanState::Quoted(c),
+                '[' => ScanState::Quoted(']'),
+                _ => state,
+            },
+        };
+    }
+
+    state
+}
+
+fn go_count(line: &str) -> anyhow::Result<Option<usize>> {
+    //! Quantas vezes o lote é executado quando a linha é um separador `GO`.
+
+    let line = line.trim();
+    let Some(rest) = line
+        .get(..2)
+        .filter(|go| go.eq_ignore_ascii_case("go"))
+        .map(|_| &line[2..])
+    else {
+        return Ok(None);
+    };
+
+    // `GOTO` e outras palavras iniciadas por `go` não são separadores
+    if !(rest.is_empty() || rest.starts_with(char::is_whitespace) || rest.starts_with("--")) {
+        return Ok(None);
+    }
+
+    let count = rest
+        .split_once("--")
+        .map_or(rest, |(count, _)| count)
+        .trim();
+    if count.is_empty() {
+        return Ok(Some(1));
+    }
+
+    match count.parse::<usize>() {
+        std::result::Result::Ok(count) if count > 0 => Ok(Some(count)),
+        _ => bail!("Separador de lotes inválido: {}", line),
+    }
+}
+
+pub async fn current_database(client: &mut Client<Compat<TcpStream>>) -> anyhow::Result<String> {
+    //! Retorna o banco de dados atual da sessão.
+
+    let row = client
+        .simple_query("SELECT DB_NAME()")
+        .await?
+        .into_row()
+        .await?
+        .context("Banco de dados atual não encontrado")?;
+
+    Ok(row.get::<&str, _>(0).unwrap_or_default().to_string())
+}
+
 pub async fn schema_mssql(
+    client: &mut Client<Compat<TcpStream>>,
     database: &str,
+    table_schema: Option<&str>,
     table_name: &str,
-    server: &str,
-    user: Option<&str>,
-    password: Option<&str>,
 ) -> anyhow::Result<Vec<MSchema>> {
     //! Retorna os metadados de uma tabela do banco.
     //! Utiliza a tabela `INFORMATION_SCHEMA.columns` para obter os metadados.
+    //! Executa na sessão do `client` recebido.
+    //! O schema e a tabela são passados como parâmetros e o banco entre colchetes,
+    //! sem schema é usado o schema padrão do usuário.
+    //! As colunas seguem a ordem de `ORDINAL_POSITION`, a mesma do `SELECT *`.
 
     let mut schema: Vec<MSchema> = Vec::new();
-    let mut client: Client<Compat<TcpStream>> = connect_server(server, user, password).await?;
+
+    validate_identifier(database)?;
 
     let sql: String = format!(
         r#"
         select
              column_name
-            ,data_type
+            ,case
+                when data_type = 'binary'
+                then concat(data_type, '(', character_maximum_length, ')')
+                else data_type
+             end as data_type
             ,is_nullable
             ,cast(numeric_precision as tinyint)  as numeric_precision
             ,cast(numeric_scale as tinyint)      as numeric_scale
             ,cast(datetime_precision as tinyint) as datetime_precision
         from {}.INFORMATION_SCHEMA.columns
-        where TABLE_NAME = '{}'
+        where TABLE_SCHEMA = coalesce(@P1, schema_name())
+          and TABLE_NAME = @P2
+        order by ORDINAL_POSITION
        "#,
-        database, table_name
+        quote_identifier(database)
     );
 
-    let select: Query<'_> = Query::new(sql);
-    let mut stream: QueryStream<'_> = select.query(&mut client).await?;
+    let mut select: Query<'_> = Query::new(sql);
+    select.bind(table_schema);
+    select.bind(table_name);
+
+    let mut stream: QueryStream<'_> = select.query(client).await?;
 
     while let Some(row) = stream.try_next().await? {
         if let QueryItem::Row(r) = row {
@@ -92,29 +857,51 @@ pub async fn schema_mssql(
     Ok(schema)
 }
 
+pub fn params_declaration(param_types: &[String]) -> String {
+    //! Monta a declaração dos parâmetros posicionais,
+    //! `@P1 int, @P2 nvarchar(4000)`, no formato do `sp_executesql`.
+
+    param_types
+        .iter()
+        .enumerate()
+        .map(|(position, sql_type)| format!("@P{} {}", position + 1, sql_type))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 pub async fn schema_mssql_query(
+    client: &mut Client<Compat<TcpStream>>,
     query: &str,
-    server: &str,
-    user: Option<&str>,
-    password: Option<&str>,
+    param_types: &[String],
 ) -> anyhow::Result<Vec<MSchema>> {
     //! Retorna os metadados da consulta,
     //! como nome da coluna, tipo de dado, se é nulo,
     //! precisão numérica, escala numérica e precisão de data e hora.
     //! Utiliza a `procedure sp_describe_first_result_set` para obter os metadados.
+    //! Executa na sessão do `client` recebido, enxergando tabelas temporárias
+    //! e opções `SET` da mesma sessão.
+    //! Os tipos de `param_types` declaram `@P1..@Pn` no argumento `@params`.
 
     let mut schema: Vec<MSchema> = Vec::new();
-    let mut client: Client<Compat<TcpStream>> = connect_server(server, user, password).await?;
 
-    let sql: String = format!(
+    let mut sql: String = format!(
         r#"
         EXEC sp_describe_first_result_set @tsql = N'{}'
        "#,
         query.replace("'", "''") // scape
     );
 
+    if !param_types.is_empty() {
+        let params = params_declaration(param_types);
+        sql = format!(
+            "{}, @params = N'{}'",
+            sql.trim_end(),
+            params.replace("'", "''")
+        );
+    }
+
     let select: Query<'_> = Query::new(sql);
-    let mut stream: QueryStream<'_> = select.query(&mut client).await?;
+    let mut stream: QueryStream<'_> = select.query(client).await?;
 
     while let Some(row) = stream.try_next().await? {
         if let QueryItem::Row(r) = row {
@@ -138,3 +925,290 @@ pub async fn schema_mssql_query(
 
     Ok(schema)
 }
+
+pub fn schema_from_metadata(columns: &[Column]) -> Vec<MSchema> {
+    //! Monta os metadados a partir das colunas recebidas no fluxo da consulta.
+    //! Usado nos conjuntos de resultados seguintes ao primeiro e quando
+    //! o `sp_describe_first_result_set` não consegue descrever a consulta.
+    //! O tiberius não expõe a precisão dos decimais, a escala das datas
+    //! nem a nulidade, então as colunas são anuláveis e a precisão
+    //! fica com o valor máximo até ser ajustada pelos dados.
+
+    columns
+        .iter()
+        .map(|column| {
+            let data_type = match column.column_type() {
+                ColumnType::Bit | ColumnType::Bitn => "bit",
+                ColumnType::Int1 => "tinyint",
+                ColumnType::Int2 => "smallint",
+                ColumnType::Int4 | ColumnType::Intn => "int",
+                ColumnType::Int8 => "bigint",
+                ColumnType::Float4 => "real",
+                ColumnType::Float8 | ColumnType::Floatn => "float",
+                ColumnType::Money => "money",
+                ColumnType::Money4 => "smallmoney",
+                ColumnType::Datetime | ColumnType::Datetimen => "datetime",
+                ColumnType::Datetime4 => "smalldatetime",
+                ColumnType::Daten => "date",
+                ColumnType::Timen => "time",
+                ColumnType::Datetime2 => "datetime2",
+                ColumnType::DatetimeOffsetn => "datetimeoffset",
+                ColumnType::Decimaln | ColumnType::Numericn => "decimal",
+                ColumnType::Guid => "uniqueidentifier",
+                ColumnType::BigVarBin => "varbinary",
+                ColumnType::BigBinary => "binary",
+                ColumnType::Image => "image",
+                ColumnType::BigVarChar => "varchar",
+                ColumnType::BigChar => "char",
+                ColumnType::Text => "text",
+                ColumnType::NVarchar | ColumnType::Null => "nvarchar",
+                ColumnType::NChar => "nchar",
+                ColumnType::NText => "ntext",
+                ColumnType::Xml => "xml",
+                // o tiberius interrompe a leitura dos metadados antes de chegar aqui
+                ColumnType::Udt | ColumnType::SSVariant => unreachable!(),
+            };
+
+            // sem escala até o primeiro valor não nulo, ver `refine_schema`
+            let (numeric_precision, numeric_scale) = match data_type {
+                "decimal" => (Some(38), None),
+                _ => (None, None),
+            };
+
+            let datetime_precision = match data_type {
+                "datetime" => Some(3),
+                "time" | "datetime2" | "datetimeoffset" => Some(7),
+                _ => Some(0),
+            };
+
+            MSchema {
+                column_name: Some(column.name().to_string()),
+                data_type: Some(data_type.to_string()),
+                is_nullable: Some("YES".to_string()),
+                numeric_precision,
+                numeric_scale,
+                datetime_precision,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, data_type: &str) -> MSchema {
+        MSchema {
+            column_name: Some(name.to_string()),
+            data_type: Some(data_type.to_string()),
+            is_nullable: Some("YES".to_string()),
+            numeric_precision: None,
+            numeric_scale: None,
+            datetime_precision: None,
+        }
+    }
+
+    fn address(host: &str, port: Option<u16>, instance: Option<&str>) -> ServerAddress {
+        ServerAddress {
+            host: host.to_string(),
+            port,
+            instance: instance.map(|i| i.to_string()),
+        }
+    }
+
+    #[test]
+    fn server_address_forms() {
+        let parse = |server| ServerAddress::parse(server).unwrap();
+
+        assert_eq!(parse("db01"), address("db01", None, None));
+        assert_eq!(parse("db01,14330"), address("db01", Some(14330), None));
+        assert_eq!(parse("db01:14330"), address("db01", Some(14330), None));
+        assert_eq!(
+            parse("tcp:db01.local, 1500"),
+            address("db01.local", Some(1500), None)
+        );
+        assert_eq!(
+            parse(r"db01\SQLEXPRESS"),
+            address("db01", None, Some("SQLEXPRESS"))
+        );
+        assert_eq!(
+            parse(r"tcp:db01\SQLEXPRESS,1500"),
+            address("db01", Some(1500), Some("SQLEXPRESS"))
+        );
+        assert_eq!(parse("[::1]:1500"), address("::1", Some(1500), None));
+        assert_eq!(parse("fe80::1"), address("fe80::1", None, None));
+        assert_eq!(parse(r"db01\"), address("db01", None, None));
+    }
+
+    #[test]
+    fn server_address_errors() {
+        assert!(ServerAddress::parse("").is_err());
+        assert!(ServerAddress::parse(",1433").is_err());
+        assert!(ServerAddress::parse("db01,porta").is_err());
+        assert!(ServerAddress::parse("db01,70000").is_err());
+    }
+
+    #[test]
+    fn dot_is_localhost() {
+        assert_eq!(ServerAddress::parse(".").unwrap().get_host(), "localhost");
+        assert_eq!(
+            ServerAddress::parse(r".\SQLEXPRESS").unwrap().get_host(),
+            "localhost"
+        );
+    }
+
+    fn object(database: Option<&str>, schema: Option<&str>, name: &str) -> ObjectName {
+        ObjectName {
+            database: database.map(|d| d.to_string()),
+            schema: schema.map(|s| s.to_string()),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn object_names_with_one_to_three_parts() {
+        let parse = |name| ObjectName::parse(name).unwrap();
+
+        assert_eq!(parse("pedidos"), object(None, None, "pedidos"));
+        assert_eq!(parse("dbo.pedidos"), object(None, Some("dbo"), "pedidos"));
+        assert_eq!(
+            parse("vendas.dbo.pedidos"),
+            object(Some("vendas"), Some("dbo"), "pedidos")
+        );
+        assert_eq!(
+            parse("vendas..pedidos"),
+            object(Some("vendas"), None, "pedidos")
+        );
+    }
+
+    #[test]
+    fn bracketed_names_keep_dots_and_brackets() {
+        let name = ObjectName::parse("[minha.base].[dbo].[pedidos]]2024]").unwrap();
+
+        assert_eq!(
+            name,
+            object(Some("minha.base"), Some("dbo"), "pedidos]2024")
+        );
+        assert_eq!(name.quoted(), "[minha.base].[dbo].[pedidos]]2024]");
+    }
+
+    #[test]
+    fn invalid_object_names() {
+        assert!(ObjectName::parse("servidor.vendas.dbo.pedidos").is_err());
+        assert!(ObjectName::parse("[dbo.pedidos").is_err());
+        assert!(ObjectName::parse("dbo.").is_err());
+        assert!(ObjectName::parse(".dbo.pedidos").is_err());
+    }
+
+    #[test]
+    fn identifiers_are_quoted() {
+        assert_eq!(quote_identifier("valor"), "[valor]");
+        assert_eq!(
+            quote_identifier("a]; DROP TABLE t --"),
+            "[a]]; DROP TABLE t --]"
+        );
+    }
+
+    #[test]
+    fn select_table_in_schema_order() {
+        let mut valor = column("valor", "money");
+        valor.numeric_precision = Some(19);
+        let schema = vec![column("id", "int"), column("nome]x", "nvarchar"), valor];
+
+        assert_eq!(
+            select_table_sql(&ObjectName::parse("vendas.dbo.pedidos").unwrap(), &schema),
+            "SELECT [id], [nome]]x], CAST([valor] AS decimal(19,4)) AS [valor] \
+             FROM [vendas].[dbo].[pedidos]"
+        );
+    }
+
+    #[test]
+    fn batches_split_on_go_lines() {
+        let script = "SET NOCOUNT ON\ngo\nSELECT 1\n  Go  \n\nGO\nSELECT 2";
+
+        assert_eq!(
+            split_batches(script).unwrap(),
+            vec!["SET NOCOUNT ON\n", "SELECT 1\n", "SELECT 2\n"]
+        );
+    }
+
+    #[test]
+    fn go_with_count_and_comment() {
+        let script = "INSERT INTO t DEFAULT VALUES\nGO 3 -- tres linhas\nSELECT 1\nGO -- fim";
+
+        assert_eq!(
+            split_batches(script).unwrap(),
+            vec![
+                "INSERT INTO t DEFAULT VALUES\n",
+                "INSERT INTO t DEFAULT VALUES\n",
+                "INSERT INTO t DEFAULT VALUES\n",
+                "SELECT 1\n",
+            ]
+        );
+        assert!(split_batches("SELECT 1\nGO x").is_err());
+        assert!(split_batches("SELECT 1\nGO 0").is_err());
+    }
+
+    #[test]
+    fn go_inside_strings_and_comments_is_kept() {
+        let script = "SELECT 'a\nGO\nb''c'\n/* x /* y\nGO\n*/\nGO\n*/ SELECT [d\nGO\n]\n\
+                      GOTO fim -- GO\nGO\nSELECT 2";
+        let batches = split_batches(script).unwrap();
+
+        assert_eq!(batches.len(), 2);
+        assert_eq!(
+            batches[0],
+            "SELECT 'a\nGO\nb''c'\n/* x /* y\nGO\n*/\nGO\n*/ SELECT [d\nGO\n]\nGOTO fim -- GO\n"
+        );
+        assert_eq!(batches[1], "SELECT 2\n");
+    }
+
+    #[test]
+    fn queries_without_driver_types_are_not_wrapped() {
+        let schema = vec![column("id", "int"), column("nome", "nvarchar(50)")];
+
+        assert!(wrap_query_sql("SELECT id, nome FROM t", &schema).is_none());
+    }
+
+    #[test]
+    fn driver_types_are_converted_around_the_query() {
+        let schema = vec![
+            column("id", "int"),
+            column("no", "hierarchyid"),
+            column("valor", "sql_variant"),
+        ];
+        let sql = wrap_query_sql("SELECT id, no, valor FROM t;\n", &schema).unwrap();
+
+        assert!(sql.starts_with(
+            "SELECT [id], CAST([no] AS varbinary(max)) AS [no], CASE CAST(SQL_VARIANT_PROPERTY("
+        ));
+        assert!(sql.contains("AS [valor], CAST(SQL_VARIANT_PROPERTY([valor], 'BaseType')"));
+        assert!(sql.ends_with(
+            "AS [valor_type] FROM (\nSELECT id, no, valor FROM t\n) AS [rustmssql_query]"
+        ));
+
+        let names: Vec<_> = variant_type_columns(schema)
+            .into_iter()
+            .filter_map(|c| c.column_name)
+            .collect();
+        assert_eq!(names, vec!["id", "no", "valor", "valor_type"]);
+    }
+
+    #[test]
+    fn money_is_read_as_decimal() {
+        let schema = vec![column("valor", "money"), column("taxa", "smallmoney")];
+
+        assert_eq!(
+            wrap_query_sql("SELECT valor, taxa FROM t", &schema).unwrap(),
+            "SELECT CAST([valor] AS decimal(19,4)) AS [valor], \
+             CAST([taxa] AS decimal(10,4)) AS [taxa] FROM (\nSELECT valor, taxa FROM t\n) AS [rustmssql_query]"
+        );
+    }
+
+    #[test]
+    fn driver_types_without_conversion_are_rejected() {
+        assert!(check_driver_types(&[column("id", "int")]).is_ok());
+        assert!(check_driver_types(&[column("local", "geography")]).is_err());
+        assert!(check_driver_types(&[column("t", "banco.dbo.tipo")]).is_err());
+    }
+}
@@ -1,13 +1,17 @@
-use crate::MSchema;
-use anyhow::Ok;
-use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use crate::{
+    BinaryFormat, GeoColumn, Geometry, MSchema, clr_to_wkb, hierarchyid_to_string, parse_bit,
+    parse_date, parse_datetime, parse_decimal, parse_time,
+};
+use anyhow::{Context, Ok, anyhow, bail};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::{Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
 use parquet::data_type::{
     BoolType, ByteArray, ByteArrayType, DoubleType, FixedLenByteArray, FixedLenByteArrayType,
     FloatType, Int32Type, Int64Type,
 };
 use parquet::file::writer::SerializedColumnWriter;
-use tiberius::ColumnData;
 use tiberius::time::DateTime;
+use tiberius::{ColumnData, Uuid};
 
 pub trait ColumnProcess<T> {
     fn process(&mut self) -> anyhow::Result<(), anyhow::Error> {
@@ -19,6 +23,22 @@ pub struct Converter<'a> {
     pub col_data: &'a Vec<ColumnData<'a>>,
     pub col_write: Option<SerializedColumnWriter<'a>>,
     pub mssql: Option<&'a MSchema>,
+    pub binary_format: BinaryFormat,
+    pub geo: Option<&'a mut GeoColumn>,
+}
+
+impl Converter<'_> {
+    fn data_type(&self) -> String {
+        //! Tipo de destino da coluna, já com as conversões de `--cast`.
+
+        self.mssql.map(|m| m.base_type()).unwrap_or_default()
+    }
+
+    fn column(&self) -> String {
+        self.mssql
+            .and_then(|m| m.column_name.clone())
+            .unwrap_or_default()
+    }
 }
 
 impl<'a> ColumnProcess<i32> for Converter<'a> {
@@ -27,40 +47,33 @@ impl<'a> ColumnProcess<i32> for Converter<'a> {
             let mut lotes: Vec<i32> = Vec::new();
             let mut levels: Vec<i16> = Vec::new();
 
-            self.col_data.iter().for_each(|f| match f {
-                ColumnData::I32(Some(valor)) => {
-                    lotes.push(*valor as i32);
-                    levels.push(1);
+            let data_type = self.data_type();
+            let base_date_parquet = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap_or_default();
+
+            for f in self.col_data.iter() {
+                let valor = match data_type.as_str() {
+                    // dias desde 1970-01-01
+                    "offset_minutes" => value_to_offset(f),
+                    "date" => value_to_date(f).map(|date| {
+                        date.map(|date| date.signed_duration_since(base_date_parquet).num_days())
+                            .map(|days| days as i32)
+                    }),
+                    _ => value_to_i64(f, &data_type).and_then(|valor| {
+                        valor
+                            .map(|valor| check_integer_range(valor, &data_type, f))
+                            .transpose()
+                    }),
                 }
-                ColumnData::I32(None) => levels.push(0),
-                ColumnData::U8(Some(valor)) => {
-                    lotes.push(*valor as i32);
-                    levels.push(1);
+                .with_context(|| format!("Coluna {}", self.column()))?;
+
+                match valor {
+                    Some(valor) => {
+                        lotes.push(valor);
+                        levels.push(1);
+                    }
+                    None => levels.push(0),
                 }
-                ColumnData::U8(None) => levels.push(0),
-                ColumnData::I16(Some(valor)) => {
-                    lotes.push(*valor as i32);
-                    levels.push(1);
-                }
-                ColumnData::I16(None) => levels.push(0),
-                ColumnData::Date(Some(dt)) => {
-                    // Criar a data a partir de `dt`
-                    let days = dt.days() as i32;
-                    let base_date_parquet = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap_or_default();
-                    let base_date_sql_server = NaiveDate::from_ymd_opt(1, 1, 1).unwrap_or_default();
-
-                    let result_date = base_date_sql_server + chrono::Duration::days(days.into());
-                    let duration = result_date
-                        .signed_duration_since(base_date_parquet)
-                        .num_days();
-
-                    let row_add = duration.try_into().unwrap_or_default();
-                    lotes.push(row_add);
-                    levels.push(1);
-                }
-                ColumnData::Date(None) => levels.push(0),
-                _ => levels.push(0),
-            });
+            }
 
             col_write_t
                 .typed::<Int32Type>()
@@ -79,14 +92,22 @@ impl<'a> ColumnProcess<f32> for Converter<'a> {
             let mut lotes: Vec<f32> = Vec::new();
             let mut levels: Vec<i16> = Vec::new();
 
-            self.col_data.iter().for_each(|f| match f {
-                ColumnData::F32(Some(valor)) => {
-                    lotes.push(*valor);
-                    levels.push(1);
+            for f in self.col_data.iter() {
+                let valor = match f {
+                    ColumnData::F32(valor) => *valor,
+                    f => value_to_f64(f)
+                        .with_context(|| format!("Coluna {}", self.column()))?
+                        .map(|valor| valor as f32),
+                };
+
+                match valor {
+                    Some(valor) => {
+                        lotes.push(valor);
+                        levels.push(1);
+                    }
+                    None => levels.push(0),
                 }
-                ColumnData::F32(None) => levels.push(0),
-                _ => levels.push(0),
-            });
+            }
 
             col_write_t
                 .typed::<FloatType>()
@@ -105,14 +126,15 @@ impl<'a> ColumnProcess<f64> for Converter<'a> {
             let mut lotes: Vec<f64> = Vec::new();
             let mut levels: Vec<i16> = Vec::new();
 
-            self.col_data.iter().for_each(|f| match f {
-                ColumnData::F64(Some(valor)) => {
-                    lotes.push(*valor);
-                    levels.push(1);
+            for f in self.col_data.iter() {
+                match value_to_f64(f).with_context(|| format!("Coluna {}", self.column()))? {
+                    Some(valor) => {
+                        lotes.push(valor);
+                        levels.push(1);
+                    }
+                    None => levels.push(0),
                 }
-                ColumnData::F64(None) => levels.push(0),
-                _ => levels.push(0),
-            });
+            }
 
             col_write_t
                 .typed::<DoubleType>()
@@ -131,19 +153,47 @@ impl<'a> ColumnProcess<ByteArray> for Converter<'a> {
             let mut lotes: Vec<ByteArray> = Vec::new();
             let mut levels: Vec<i16> = Vec::new();
 
-            self.col_data.iter().for_each(|f| match f {
-                ColumnData::String(Some(valor)) => {
-                    lotes.push(ByteArray::from(valor.to_string().as_str()));
-                    levels.push(1);
-                }
-                ColumnData::String(None) => levels.push(0),
-                ColumnData::Xml(Some(valor)) => {
-                    lotes.push(ByteArray::from(valor.to_string().as_str()));
-                    levels.push(1);
+            // bytes sem conversão apenas nas colunas binárias sem `--cast`,
+            // nas colunas de texto o binário vira hexadecimal
+            let data_type = self.data_type();
+            let binary_format = match data_type.as_str() {
+                "binary" | "varbinary" | "image" => self.binary_format,
+                _ if self.binary_format == BinaryFormat::Raw => BinaryFormat::Hex,
+                _ => self.binary_format,
+            };
+
+            for f in self.col_data.iter() {
+                let valor = match (data_type.as_str(), f) {
+                    ("geography" | "geometry", _) => value_to_wkb(f, &data_type)
+                        .with_context(|| format!("Coluna {}", self.column()))?
+                        .map(|geometry| {
+                            if let Some(geo) = self.geo.as_mut() {
+                                geo.add(&geometry);
+                            }
+                            geometry.wkb
+                        }),
+                    ("hierarchyid", ColumnData::Binary(v)) => v
+                        .as_ref()
+                        .map(|b| hierarchyid_to_string(b))
+                        .transpose()
+                        .with_context(|| format!("Coluna {}", self.column()))?
+                        .map(String::into_bytes),
+                    (_, ColumnData::Binary(v)) => {
+                        v.as_ref().map(|b| encode_binary(b, binary_format))
+                    }
+                    _ => value_to_string(f)
+                        .with_context(|| format!("Coluna {}", self.column()))?
+                        .map(String::into_bytes),
+                };
+
+                match valor {
+                    Some(valor) => {
+                        lotes.push(ByteArray::from(valor));
+                        levels.push(1);
+                    }
+                    None => levels.push(0),
                 }
-                ColumnData::Xml(None) => levels.push(0),
-                _ => levels.push(0),
-            });
+            }
 
             col_write_t.typed::<ByteArrayType>().write_batch(
                 &lotes[..],
@@ -164,14 +214,15 @@ impl<'a> ColumnProcess<bool> for Converter<'a> {
             let mut lotes: Vec<bool> = Vec::new();
             let mut levels: Vec<i16> = Vec::new();
 
-            self.col_data.iter().for_each(|f| match f {
-                ColumnData::Bit(Some(valor)) => {
-                    lotes.push(*valor);
-                    levels.push(1);
+            for f in self.col_data.iter() {
+                match value_to_bool(f).with_context(|| format!("Coluna {}", self.column()))? {
+                    Some(valor) => {
+                        lotes.push(valor);
+                        levels.push(1);
+                    }
+                    None => levels.push(0),
                 }
-                ColumnData::Bit(None) => levels.push(0),
-                _ => levels.push(0),
-            });
+            }
 
             col_write_t
                 .typed::<BoolType>()
@@ -190,54 +241,36 @@ impl<'a> ColumnProcess<i64> for Converter<'a> {
             let mut lotes: Vec<i64> = Vec::new();
             let mut levels: Vec<i16> = Vec::new();
 
+            let data_type = self.data_type();
             let precision = self.mssql.as_ref().unwrap().datetime_precision.unwrap_or(0) as u32;
 
-            self.col_data.iter().for_each(|f| match f {
-                ColumnData::I64(Some(valor)) => {
-                    lotes.push(*valor);
-                    levels.push(1);
+            for f in self.col_data.iter() {
+                let valor = match data_type.as_str() {
+                    "datetime" | "datetime2" | "smalldatetime" | "datetimeoffset" => {
+                        value_to_datetime(f).and_then(|v| {
+                            v.map(|datetime| timestamp(datetime, precision, &self.column()))
+                                .transpose()
+                        })
+                    }
+                    // nanossegundos desde a meia-noite, microssegundos até a precisão 6
+                    "time" => value_to_time(f).map(|v| {
+                        v.map(|nanos| match precision {
+                            0..=6 => nanos / 1_000,
+                            7.. => nanos,
+                        })
+                    }),
+                    _ => value_to_i64(f, &data_type),
                 }
-                ColumnData::I64(None) => levels.push(0),
-                ColumnData::DateTime(Some(dt)) => {
-                    let datetime = convert_to_naive_datetime(dt);
-
-                    let row_add = match precision {
-                        0..=3 => datetime.and_utc().timestamp_millis(),
-                        4..=6 => datetime.and_utc().timestamp_micros(),
-                        7.. => datetime.and_utc().timestamp_nanos_opt().unwrap_or_default(),
-                    };
-
-                    lotes.push(row_add);
-                    levels.push(1);
+                .with_context(|| format!("Coluna {}", self.column()))?;
+
+                match valor {
+                    Some(valor) => {
+                        lotes.push(valor);
+                        levels.push(1);
+                    }
+                    None => levels.push(0),
                 }
-                ColumnData::DateTime(None) => levels.push(0),
-                ColumnData::DateTime2(Some(dt)) => {
-                    let days = dt.date().days().into();
-                    let increments = dt.time().increments() as i64;
-                    let scale = dt.time().scale() as u32;
-
-                    let datetime = convert_to_naive_datetime2(days, increments, scale);
-                    let row_add = match precision {
-                        0..=3 => datetime.and_utc().timestamp_millis(),
-                        4..=6 => datetime.and_utc().timestamp_micros(),
-                        7.. => datetime.and_utc().timestamp_nanos_opt().unwrap_or_default(),
-                    };
-
-                    lotes.push(row_add);
-                    levels.push(1);
-                }
-                ColumnData::DateTime2(None) => levels.push(0),
-                ColumnData::Time(Some(dt)) => {
-                    let increments = dt.increments() as i64;
-                    let scale = dt.scale() as u32;
-                    let nanos = increments * 10i64.pow(9 - scale);
-
-                    lotes.push(nanos);
-                    levels.push(1);
-                }
-                ColumnData::Time(None) => levels.push(0),
-                _ => levels.push(0),
-            });
+            }
 
             col_write_t
                 .typed::<Int64Type>()
@@ -256,27 +289,50 @@ impl<'a> ColumnProcess<FixedLenByteArray> for Converter<'a> {
             let mut lotes: Vec<FixedLenByteArray> = Vec::new();
             let mut levels: Vec<i16> = Vec::new();
 
-            let precision = self.mssql.as_ref().unwrap().numeric_precision.unwrap_or(0) as u32;
+            // money e smallmoney usam a precisão fixa de `decimal_precision`
+            let (precision, scale) = self.mssql.as_ref().unwrap().decimal_precision();
+            let precision = precision as u32;
             let num_binary_digits = precision as f64 * 10f64.log2();
             let length_in_bits = num_binary_digits + 1.0;
             let length_in_bytes = (length_in_bits / 8.0).ceil() as usize;
 
-            self.col_data.iter().for_each(|f| match f {
-                ColumnData::Numeric(Some(v)) => {
-                    let bytes_array = v.value();
-
-                    let bytes_decimal: Vec<u8> =
-                        encode_decimal(bytes_array, precision, length_in_bytes);
-
-                    let row_add = FixedLenByteArray::from(ByteArray::from(bytes_decimal));
-                    lotes.push(row_add);
-                    levels.push(1);
+            let data_type = self.data_type();
+            // decimal dos metadados do fluxo sem nenhum valor no primeiro lote
+            let unknown_scale =
+                data_type == "decimal" && self.mssql.unwrap().numeric_scale.is_none();
+
+            for f in self.col_data.iter() {
+                let valor = match data_type.as_str() {
+                    "uniqueidentifier" => {
+                        value_to_uuid(f).map(|v| v.map(|uuid| uuid.as_bytes().to_vec()))
+                    }
+                    "binary" => value_to_binary(f, self.mssql.unwrap().binary_length()),
+                    "money" | "smallmoney" => {
+                        value_to_money(f, precision as u8, scale).and_then(|valor| {
+                            valor
+                                .map(|v| encode_decimal(v, precision, length_in_bytes))
+                                .transpose()
+                        })
+                    }
+                    _ => value_to_decimal(f, precision as u8, scale, unknown_scale).and_then(
+                        |valor| {
+                            valor
+                                .map(|v| encode_decimal(v, precision, length_in_bytes))
+                                .transpose()
+                        },
+                    ),
                 }
-                ColumnData::Numeric(None) => {
-                    levels.push(0);
+                .with_context(|| format!("Coluna {}", self.column()))?;
+
+                match valor {
+                    Some(bytes) => {
+                        let row_add = FixedLenByteArray::from(ByteArray::from(bytes));
+                        lotes.push(row_add);
+                        levels.push(1);
+                    }
+                    None => levels.push(0),
                 }
-                _ => levels.push(0),
-            });
+            }
 
             col_write_t.typed::<FixedLenByteArrayType>().write_batch(
                 &lotes[..],
@@ -301,7 +357,381 @@ where
     Ok(())
 }
 
-fn encode_decimal(scaled_value: i128, precision: u32, length_in_bytes: usize) -> Vec<u8> {
+fn cannot_convert(value: &ColumnData<'_>, target: &str) -> anyhow::Error {
+    let valor = match value_to_string(value) {
+        std::result::Result::Ok(Some(valor)) => valor,
+        _ => format!("{:?}", value),
+    };
+
+    anyhow!("Valor {} não pode ser convertido para {}", valor, target)
+}
+
+fn timestamp(datetime: NaiveDateTime, precision: u32, column: &str) -> anyhow::Result<i64> {
+    //! Timestamp na unidade da precisão, em nanossegundos o INT64
+    //! cobre apenas os anos de 1677 a 2262.
+
+    let datetime = datetime.and_utc();
+    Ok(match precision {
+        0..=3 => datetime.timestamp_millis(),
+        4..=6 => datetime.timestamp_micros(),
+        7.. => datetime.timestamp_nanos_opt().with_context(|| {
+            format!(
+                "Valor {} fora do intervalo de TIMESTAMP em nanossegundos (1677 a 2262), \
+                 use --cast {}=datetime2(6)",
+                datetime.naive_utc(),
+                column
+            )
+        })?,
+    })
+}
+
+fn check_integer_range(valor: i64, data_type: &str, value: &ColumnData<'_>) -> anyhow::Result<i32> {
+    //! Verifica se o inteiro cabe no tipo de destino.
+
+    let range = match data_type {
+        "tinyint" => 0..=u8::MAX as i64,
+        "smallint" => i16::MIN as i64..=i16::MAX as i64,
+        _ => i32::MIN as i64..=i32::MAX as i64,
+    };
+
+    if !range.contains(&valor) {
+        return Err(cannot_convert(value, data_type));
+    }
+
+    Ok(valor as i32)
+}
+
+fn value_to_i64(value: &ColumnData<'_>, target: &str) -> anyhow::Result<Option<i64>> {
+    //! Converte para inteiro, sem descartar casas decimais.
+
+    Ok(match value {
+        ColumnData::U8(v) => v.map(i64::from),
+        ColumnData::I16(v) => v.map(i64::from),
+        ColumnData::I32(v) => v.map(i64::from),
+        ColumnData::I64(v) => *v,
+        ColumnData::Bit(v) => v.map(i64::from),
+        ColumnData::F32(Some(v)) if v.fract() == 0.0 => Some(*v as i64),
+        ColumnData::F64(Some(v)) if v.fract() == 0.0 && v.abs() < i64::MAX as f64 => {
+            Some(*v as i64)
+        }
+        ColumnData::F32(None) | ColumnData::F64(None) | ColumnData::Numeric(None) => None,
+        ColumnData::Numeric(Some(n)) if n.dec_part() == 0 => {
+            Some(i64::try_from(n.int_part()).map_err(|_| cannot_convert(value, target))?)
+        }
+        ColumnData::String(Some(s)) => Some(
+            s.trim()
+                .parse()
+                .map_err(|_| cannot_convert(value, target))?,
+        ),
+        ColumnData::String(None) => None,
+        _ => return Err(cannot_convert(value, target)),
+    })
+}
+
+fn value_to_f64(value: &ColumnData<'_>) -> anyhow::Result<Option<f64>> {
+    Ok(match value {
+        ColumnData::F32(v) => v.map(f64::from),
+        ColumnData::F64(v) => *v,
+        ColumnData::Numeric(v) => v.map(|n| n.value() as f64 / 10f64.powi(n.scale() as i32)),
+        ColumnData::String(Some(s)) => Some(
+            s.trim()
+                .parse()
+                .map_err(|_| cannot_convert(value, "float"))?,
+        ),
+        ColumnData::String(None) => None,
+        _ => value_to_i64(value, "float")?.map(|v| v as f64),
+    })
+}
+
+fn value_to_bool(value: &ColumnData<'_>) -> anyhow::Result<Option<bool>> {
+    Ok(match value {
+        ColumnData::Bit(v) => *v,
+        ColumnData::String(Some(s)) => {
+            Some(parse_bit(s.trim()).map_err(|_| cannot_convert(value, "bit"))?)
+        }
+        ColumnData::String(None) => None,
+        _ => match value_to_i64(value, "bit")? {
+            Some(0) => Some(false),
+            Some(1) => Some(true),
+            Some(_) => return Err(cannot_convert(value, "bit")),
+            None => None,
+        },
+    })
+}
+
+fn value_to_decimal(
+    value: &ColumnData<'_>,
+    precision: u8,
+    scale: u8,
+    unknown_scale: bool,
+) -> anyhow::Result<Option<i128>> {
+    //! Converte para o inteiro escalado de um `decimal(precision,scale)`.
+    //! Decimais com mais casas são arredondados, metade para longe do zero.
+    //! Com `unknown_scale` a escala foi assumida e perder casas é um erro.
+
+    let target = || format!("decimal({},{})", precision, scale);
+
+    if let ColumnData::Numeric(Some(n)) = value {
+        let factor = 10i128.pow(n.scale().saturating_sub(scale) as u32);
+        if unknown_scale && n.value() % factor != 0 {
+            bail!(
+                "Valor {} tem mais casas decimais que a coluna {}, \
+                 a escala não foi definida pelos primeiros registros, \
+                 informe com --cast coluna=decimal(p,s)",
+                n,
+                target()
+            );
+        }
+    }
+
+    Ok(match value {
+        ColumnData::Numeric(Some(n)) => Some(
+            rescale(n.value(), n.scale(), scale).ok_or_else(|| cannot_convert(value, &target()))?,
+        ),
+        ColumnData::Numeric(None) => None,
+        ColumnData::F32(Some(_)) | ColumnData::F64(Some(_)) => {
+            let v = value_to_f64(value)?.unwrap_or_default() * 10f64.powi(scale as i32);
+            if !v.is_finite() || v.abs() >= i128::MAX as f64 {
+                return Err(cannot_convert(value, &target()));
+            }
+            Some(v.round() as i128)
+        }
+        ColumnData::F32(None) | ColumnData::F64(None) => None,
+        ColumnData::String(Some(s)) => Some(
+            parse_decimal(s.trim(), precision, scale)
+                .with_context(|| cannot_convert(value, &target()))?
+                .value(),
+        ),
+        ColumnData::String(None) => None,
+        _ => value_to_i64(value, &target())?
+            .map(|v| rescale(v as i128, 0, scale).ok_or_else(|| cannot_convert(value, &target())))
+            .transpose()?,
+    })
+}
+
+fn value_to_money(
+    value: &ColumnData<'_>,
+    precision: u8,
+    scale: u8,
+) -> anyhow::Result<Option<i128>> {
+    //! `money` convertido no servidor chega como decimal e é exato.
+    //! Sem a conversão o tiberius entrega o inteiro do TDS dividido por 10.000
+    //! em um f64, que só volta ao inteiro original abaixo de 2^50,
+    //! cerca de ±112 bilhões; acima disso a exportação falha.
+
+    if let ColumnData::F64(Some(v)) = value {
+        if (v * 10_000.0).abs() >= 2f64.powi(50) {
+            bail!(
+                "Valor money {} não pode ser recuperado sem perda de precisão, \
+                 use CAST(coluna AS decimal(19,4)) na consulta",
+                v
+            );
+        }
+    }
+
+    value_to_decimal(value, precision, scale, false)
+}
+
+fn rescale(value: i128, from: u8, to: u8) -> Option<i128> {
+    //! Muda a escala do inteiro de um decimal.
+
+    if to >= from {
+        return value.checked_mul(10i128.checked_pow((to - from) as u32)?);
+    }
+
+    let factor = 10i128.checked_pow((from - to) as u32)?;
+    let quotient = value / factor;
+    let remainder = (value % factor).abs();
+
+    Some(if remainder * 2 >= factor {
+        quotient + value.signum()
+    } else {
+        quotient
+    })
+}
+
+fn value_to_datetime(value: &ColumnData<'_>) -> anyhow::Result<Option<NaiveDateTime>> {
+    Ok(match value {
+        ColumnData::DateTime(v) => v.as_ref().map(convert_to_naive_datetime),
+        ColumnData::SmallDateTime(v) => v.map(|dt| {
+            // dias desde 1900-01-01 e minutos desde a meia-noite
+            NaiveDate::from_ymd_opt(1900, 1, 1)
+                .unwrap_or_default()
+                .and_time(NaiveTime::MIN)
+                + Duration::days(dt.days().into())
+                + Duration::minutes(dt.seconds_fragments().into())
+        }),
+        ColumnData::DateTime2(v) => v.map(|dt| {
+            convert_to_naive_datetime2(
+                dt.date().days().into(),
+                dt.time().increments() as i64,
+                dt.time().scale() as u32,
+            )
+        }),
+        // o TDS transmite a data e hora do datetimeoffset em UTC
+        ColumnData::DateTimeOffset(v) => v.map(|dto| {
+            let dt = dto.datetime2();
+            convert_to_naive_datetime2(
+                dt.date().days().into(),
+                dt.time().increments() as i64,
+                dt.time().scale() as u32,
+            )
+        }),
+        ColumnData::Date(v) => {
+            v.map(|dt| convert_to_naive_date(dt.days()).and_time(NaiveTime::MIN))
+        }
+        ColumnData::String(Some(s)) => {
+            Some(parse_datetime(s.trim()).map_err(|_| cannot_convert(value, "datetime2"))?)
+        }
+        ColumnData::String(None) => None,
+        _ => return Err(cannot_convert(value, "datetime2")),
+    })
+}
+
+fn value_to_date(value: &ColumnData<'_>) -> anyhow::Result<Option<NaiveDate>> {
+    Ok(match value {
+        ColumnData::Date(v) => v.map(|dt| convert_to_naive_date(dt.days())),
+        ColumnData::String(Some(s)) => Some(
+            parse_date(s.trim())
+                .or_else(|_| parse_datetime(s.trim()).map(|dt| dt.date()))
+                .map_err(|_| cannot_convert(value, "date"))?,
+        ),
+        _ => value_to_datetime(value)
+            .map_err(|_| cannot_convert(value, "date"))?
+            .map(|dt| dt.date()),
+    })
+}
+
+fn value_to_offset(value: &ColumnData<'_>) -> anyhow::Result<Option<i32>> {
+    //! Fuso do datetimeoffset em minutos, `-03:00` vira `-180`.
+
+    Ok(match value {
+        ColumnData::DateTimeOffset(v) => v.map(|dto| dto.offset() as i32),
+        _ => return Err(cannot_convert(value, "offset")),
+    })
+}
+
+fn value_to_time(value: &ColumnData<'_>) -> anyhow::Result<Option<i64>> {
+    //! Nanossegundos desde a meia-noite.
+
+    let nanos = |time: NaiveTime| {
+        time.num_seconds_from_midnight() as i64 * 1_000_000_000 + time.nanosecond() as i64
+    };
+
+    Ok(match value {
+        ColumnData::Time(v) => v.map(|dt| {
+            let increments = dt.increments() as i64;
+            let scale = dt.scale() as u32;
+            increments * 10i64.pow(9 - scale)
+        }),
+        ColumnData::String(Some(s)) => Some(nanos(
+            parse_time(s.trim()).map_err(|_| cannot_convert(value, "time"))?,
+        )),
+        ColumnData::String(None) => None,
+        _ => value_to_datetime(value)
+            .map_err(|_| cannot_convert(value, "time"))?
+            .map(|dt| nanos(dt.time())),
+    })
+}
+
+fn value_to_uuid(value: &ColumnData<'_>) -> anyhow::Result<Option<Uuid>> {
+    //! O SQL Server grava os três primeiros grupos do GUID em little-endian,
+    //! o tiberius já reordena os bytes e o `Uuid` fica na ordem da RFC 4122,
+    //! a mesma do texto exibido pelo servidor e do tipo UUID do parquet.
+
+    Ok(match value {
+        ColumnData::Guid(v) => *v,
+        ColumnData::String(Some(s)) => {
+            Some(Uuid::parse_str(s.trim()).map_err(|_| cannot_convert(value, "uniqueidentifier"))?)
+        }
+        ColumnData::String(None) => None,
+        _ => return Err(cannot_convert(value, "uniqueidentifier")),
+    })
+}
+
+fn value_to_binary(
+    value: &ColumnData<'_>,
+    length: Option<usize>,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    //! Bytes de `binary(n)`, o SQL Server completa o valor com zeros
+    //! até o tamanho da coluna.
+
+    Ok(match value {
+        ColumnData::Binary(Some(bytes)) => {
+            if length.is_some_and(|length| length != bytes.len()) {
+                return Err(cannot_convert(value, "binary"));
+            }
+            Some(bytes.to_vec())
+        }
+        ColumnData::Binary(None) => None,
+        _ => return Err(cannot_convert(value, "binary")),
+    })
+}
+
+fn value_to_wkb(value: &ColumnData<'_>, data_type: &str) -> anyhow::Result<Option<Geometry>> {
+    //! Geometrias chegam como `varbinary` com a serialização do SQL Server.
+
+    Ok(match value {
+        ColumnData::Binary(Some(bytes)) => Some(clr_to_wkb(bytes, data_type == "geography")?),
+        ColumnData::Binary(None) => None,
+        _ => return Err(cannot_convert(value, data_type)),
+    })
+}
+
+fn encode_binary(bytes: &[u8], format: BinaryFormat) -> Vec<u8> {
+    match format {
+        BinaryFormat::Raw => bytes.to_vec(),
+        BinaryFormat::Hex => hex(bytes).into_bytes(),
+        BinaryFormat::Base64 => STANDARD.encode(bytes).into_bytes(),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn value_to_string(value: &ColumnData<'_>) -> anyhow::Result<Option<String>> {
+    Ok(match value {
+        ColumnData::String(v) => v.as_ref().map(|v| v.to_string()),
+        ColumnData::Xml(v) => v.as_ref().map(|v| v.to_string()),
+        ColumnData::U8(v) => v.map(|v| v.to_string()),
+        ColumnData::I16(v) => v.map(|v| v.to_string()),
+        ColumnData::I32(v) => v.map(|v| v.to_string()),
+        ColumnData::I64(v) => v.map(|v| v.to_string()),
+        ColumnData::F32(v) => v.map(|v| v.to_string()),
+        ColumnData::F64(v) => v.map(|v| v.to_string()),
+        ColumnData::Bit(v) => v.map(|v| v.to_string()),
+        ColumnData::Numeric(v) => v.map(|v| v.to_string()),
+        ColumnData::Guid(v) => v.map(|v| v.to_string()),
+        ColumnData::Binary(v) => v.as_ref().map(|v| hex(v)),
+        ColumnData::Date(v) => v.map(|dt| convert_to_naive_date(dt.days()).to_string()),
+        ColumnData::Time(_) => value_to_time(value)?.map(|nanos| {
+            (NaiveTime::MIN + Duration::nanoseconds(nanos))
+                .format("%H:%M:%S%.f")
+                .to_string()
+        }),
+        ColumnData::DateTimeOffset(Some(dto)) => {
+            let offset = FixedOffset::east_opt(dto.offset() as i32 * 60)
+                .ok_or_else(|| cannot_convert(value, "datetimeoffset"))?;
+            value_to_datetime(value)?.map(|dt| {
+                dt.and_utc()
+                    .with_timezone(&offset)
+                    .format("%Y-%m-%d %H:%M:%S%.f %:z")
+                    .to_string()
+            })
+        }
+        ColumnData::DateTimeOffset(None) => None,
+        ColumnData::DateTime(_) | ColumnData::SmallDateTime(_) | ColumnData::DateTime2(_) => {
+            value_to_datetime(value)?.map(|dt| dt.format("%Y-%m-%d %H:%M:%S%.f").to_string())
+        }
+    })
+}
+
+fn encode_decimal(
+    scaled_value: i128,
+    precision: u32,
+    length_in_bytes: usize,
+) -> anyhow::Result<Vec<u8>> {
     // Converter a string para um número de ponto flutuante
     //!let float_value: f64 = value.parse().expect("Invalid decimal string");
 
@@ -314,9 +744,10 @@ fn encode_decimal(scaled_value: i128, precision: u32, length_in_bytes: usize) ->
     let min_value = -10i128.pow(precision);
 
     if scaled_value > max_value || scaled_value < min_value {
-        panic!(
+        bail!(
             "Valor escalado ({}) excede o intervalo permitido para a precisão {}",
-            scaled_value, precision
+            scaled_value,
+            precision
         );
     }
 
@@ -336,7 +767,13 @@ fn encode_decimal(scaled_value: i128, precision: u32, length_in_bytes: usize) ->
 
     bytes[dest_start..].copy_from_slice(&scaled_bytes[copy_start..copy_end]);
 
-    bytes
+    Ok(bytes)
+}
+
+fn convert_to_naive_date(days: u32) -> NaiveDate {
+    //! Dias desde 0001-01-01, base do tipo `date`.
+
+    NaiveDate::from_ymd_opt(1, 1, 1).unwrap_or_default() + Duration::days(days.into())
 }
 
 fn convert_to_naive_datetime(dt: &DateTime) -> NaiveDateTime {
@@ -372,11 +809,8 @@ fn convert_to_naive_datetime(dt: &DateTime) -> NaiveDateTime {
     let clamped_milliseconds = rounded_milliseconds.min(999);
 
     // Cria um novo NaiveDateTime com os milissegundos arredondados
-    let finaly = date
-        .with_nanosecond(clamped_milliseconds * 1_000_000)
-        .unwrap_or_default();
-
-    finaly
+    date.with_nanosecond(clamped_milliseconds * 1_000_000)
+        .unwrap_or_default()
 }
 
 fn convert_to_naive_datetime2(days: i64, increments: i64, scale: u32) -> NaiveDateTime {
@@ -392,7 +826,57 @@ fn convert_to_naive_datetime2(days: i64, increments: i64, scale: u32) -> NaiveDa
     let time = NaiveTime::from_hms_opt(0, 0, 0).unwrap_or_default()
         + Duration::nanoseconds(fractional_nanoseconds);
 
-    let datetime = NaiveDateTime::new(date, time);
+    NaiveDateTime::new(date, time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nanosecond_timestamp_out_of_range_is_an_error() {
+        let sentinel = NaiveDate::from_ymd_opt(9999, 12, 31)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+
+        assert!(timestamp(sentinel, 7, "validade").is_err());
+        assert_eq!(
+            timestamp(sentinel, 6, "validade").unwrap(),
+            sentinel.and_utc().timestamp_micros()
+        );
+    }
+
+    #[test]
+    fn decimal_with_unknown_scale_is_not_rounded() {
+        let value = ColumnData::Numeric(Some(tiberius::numeric::Numeric::new_with_scale(12345, 2)));
+        let integer =
+            ColumnData::Numeric(Some(tiberius::numeric::Numeric::new_with_scale(12300, 2)));
+
+        assert!(value_to_decimal(&value, 38, 0, true).is_err());
+        assert_eq!(value_to_decimal(&integer, 38, 0, true).unwrap(), Some(123));
+        // com a escala informada por `--cast` o valor é arredondado
+        assert_eq!(value_to_decimal(&value, 38, 1, false).unwrap(), Some(1235));
+    }
+
+    #[test]
+    fn money_is_exact_or_an_error() {
+        let decimal = ColumnData::Numeric(Some(tiberius::numeric::Numeric::new_with_scale(
+            9_223_372_036_854_775_807,
+            4,
+        )));
+        assert_eq!(
+            value_to_money(&decimal, 19, 4).unwrap(),
+            Some(9_223_372_036_854_775_807)
+        );
 
-    datetime
+        let small = ColumnData::F64(Some(123456789.1234));
+        assert_eq!(
+            value_to_money(&small, 19, 4).unwrap(),
+            Some(1_234_567_891_234)
+        );
+
+        let large = ColumnData::F64(Some(500000000000.0001));
+        assert!(value_to_money(&large, 19, 4).is_err());
+    }
 }
@@ -0,0 +1,107 @@
+use crate::ConnectionOptions;
+use anyhow::{Context, Ok, bail};
+use std::io::IsTerminal;
+use std::{env, fs, path::Path};
+
+/// Variável de ambiente com o nome do usuário.
+pub const ENV_USER: &str = "RUSTMSSQL_USER";
+
+/// Variável de ambiente com a senha de acesso.
+pub const ENV_PASSWORD: &str = "RUSTMSSQL_PASSWORD";
+
+pub fn resolve_credentials(
+    options: &mut ConnectionOptions,
+    password_file: Option<&Path>,
+) -> anyhow::Result<()> {
+    //! Completa o usuário e a senha das opções de conexão.
+    //! A senha é buscada, nesta ordem, no arquivo de senha,
+    //! na variável `RUSTMSSQL_PASSWORD` e por fim no terminal, sem eco.
+    //! Sem usuário a conexão usa a autenticação integrada.
+
+    if options.user.is_none() {
+        options.user = env::var(ENV_USER).ok().filter(|u| !u.is_empty());
+    }
+
+    let Some(user) = options.user.as_deref() else {
+        if password_file.is_some() {
+            bail!("Arquivo de senha informado sem usuário");
+        }
+        return Ok(());
+    };
+
+    if options.password.is_some() {
+        return Ok(());
+    }
+
+    let password = if let Some(path) = password_file {
+        read_password_file(path)?
+    } else if let Some(password) = env::var(ENV_PASSWORD).ok().filter(|p| !p.is_empty()) {
+        password
+    } else if std::io::stdin().is_terminal() {
+        rpassword::prompt_password(format!("Senha de {}: ", user))?
+    } else {
+        bail!(
+            "Senha não informada para o usuário {}, use --password-file ou {}",
+            user,
+            ENV_PASSWORD
+        );
+    };
+
+    options.password = Some(password);
+
+    Ok(())
+}
+
+fn read_password_file(path: &Path) -> anyhow::Result<String> {
+    //! Lê a senha da primeira linha do arquivo.
+    //! No unix o arquivo não pode ter permissão de leitura para grupo ou outros.
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = fs::metadata(path)
+            .with_context(|| format!("Arquivo de senha {} não encontrado", path.display()))?
+            .permissions()
+            .mode();
+
+        if mode & 0o077 != 0 {
+            bail!(
+                "Arquivo de senha {} com permissões {:o} muito abertas, use `chmod 600`",
+                path.display(),
+                mode & 0o777
+            );
+        }
+    }
+
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Falha ao ler o arquivo de senha {}", path.display()))?;
+
+    let password = content.lines().next().unwrap_or_default();
+    if password.is_empty() {
+        bail!("Arquivo de senha {} vazio", path.display());
+    }
+
+    Ok(password.to_string())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn password_file_must_not_be_readable_by_others() {
+        let path = std::env::temp_dir().join(format!("rustmssql_senha_{}", std::process::id()));
+        fs::write(&path, "abcd.1234\nsegunda linha\n").unwrap();
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let error = read_password_file(&path).unwrap_err();
+        assert!(error.to_string().contains("644"));
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(read_password_file(&path).unwrap(), "abcd.1234");
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,189 @@
+use anyhow::bail;
+
+/// Padrões de bits dos rótulos do `hierarchyid` (MS-SSCLRT), com o menor valor
+/// de cada faixa. `x` são os bits do valor, `0` e `1` são fixos e `T` indica
+/// se o rótulo encerra o nível (`/`) ou é seguido por `.`.
+const PATTERNS: &[(&str, i64)] = &[
+    ("01xxT", 0),
+    ("100xxT", 4),
+    ("101xxxT", 8),
+    ("110xx0x1xxxT", 16),
+    ("1110xxx0xxx0x1xxxT", 80),
+    ("11110xxxxx0xxx0x1xxxT", 1104),
+    ("111110xxxxxxxxxxxxxxxxxxx0xxxxxx0xxx0x1xxxT", 5200),
+    (
+        "111111xxxxxxxxxxxxxx0xxxxxxxxxxxxxxxxxxxxx0xxxxxx0xxx0x1xxxT",
+        4294972496,
+    ),
+    ("00111xxxT", -8),
+    ("0010xx0x1xxxT", -72),
+    ("000110xxxxx0xxx0x1xxxT", -4168),
+    ("000101xxxxxxxxxxxxxxxxxxx0xxxxxx0xxx0x1xxxT", -4294971464),
+    (
+        "000100xxxxxxxxxxxxxx0xxxxxxxxxxxxxxxxxxxxx0xxxxxx0xxx0x1xxxT",
+        -281479271682120,
+    ),
+];
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn remaining(&self) -> usize {
+        self.bytes.len() * 8 - self.position
+    }
+
+    fn peek(&self, offset: usize) -> bool {
+        let position = self.position + offset;
+        self.bytes[position / 8] & (0x80 >> (position % 8)) != 0
+    }
+
+    fn only_padding(&self) -> bool {
+        //! O último byte é completado com zeros.
+
+        (0..self.remaining()).all(|offset| !self.peek(offset))
+    }
+}
+
+pub fn hierarchyid_to_string(bytes: &[u8]) -> anyhow::Result<String> {
+    //! Decodifica o `hierarchyid` serializado para o caminho canônico,
+    //! o mesmo de `ToString()`, como `/1/3.2/`. O valor vazio é a raiz `/`.
+    //! Rótulos seguidos por `.` são gravados somados de 1.
+
+    let mut reader = BitReader { bytes, position: 0 };
+    let mut path = String::from("/");
+
+    while !reader.only_padding() {
+        let Some((pattern, min)) = PATTERNS.iter().find(|(pattern, _)| {
+            let prefix = pattern.find('x').unwrap_or(pattern.len());
+            prefix <= reader.remaining()
+                && pattern[..prefix]
+                    .chars()
+                    .enumerate()
+                    .all(|(offset, bit)| reader.peek(offset) == (bit == '1'))
+        }) else {
+            bail!("Valor hierarchyid inválido");
+        };
+
+        if pattern.len() > reader.remaining() {
+            bail!("Valor hierarchyid inválido");
+        }
+
+        let mut value: i64 = 0;
+        let mut last = false;
+        for (offset, bit) in pattern.chars().enumerate() {
+            match bit {
+                'x' => value = value << 1 | reader.peek(offset) as i64,
+                'T' => last = reader.peek(offset),
+                _ => {}
+            }
+        }
+        reader.position += pattern.len();
+
+        if !last {
+            value -= 1;
+        }
+        path.push_str(&(value + min).to_string());
+        path.push(if last { '/' } else { '.' });
+    }
+
+    if !path.ends_with('/') {
+        bail!("Valor hierarchyid inválido");
+    }
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(labels: &[(i64, bool)]) -> Vec<u8> {
+        //! Monta o valor serializado pelos padrões, rótulos seguidos por `.`
+        //! somados de 1.
+
+        let mut bits: Vec<bool> = Vec::new();
+        for &(label, last) in labels {
+            let stored = label + !last as i64;
+            let (pattern, min) = PATTERNS
+                .iter()
+                .filter(|(_, min)| *min <= stored)
+                .max_by_key(|(_, min)| *min)
+                .unwrap();
+            let value = (stored - min) as u64;
+            let mut bit = pattern.matches('x').count();
+            for c in pattern.chars() {
+                match c {
+                    'x' => {
+                        bit -= 1;
+                        bits.push(value >> bit & 1 == 1);
+                    }
+                    'T' => bits.push(last),
+                    c => bits.push(c == '1'),
+                }
+            }
+        }
+
+        bits.chunks(8)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0u8, |byte, (i, bit)| byte | (*bit as u8) << (7 - i))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn known_encodings() {
+        assert_eq!(hierarchyid_to_string(&[]).unwrap(), "/");
+        assert_eq!(hierarchyid_to_string(&[0x58]).unwrap(), "/1/");
+        assert_eq!(hierarchyid_to_string(&[0x5a, 0xc0]).unwrap(), "/1/1/");
+        assert_eq!(hierarchyid_to_string(&[0x3f, 0x80]).unwrap(), "/-1/");
+        assert_eq!(hierarchyid_to_string(&[0x62, 0xc0]).unwrap(), "/1.1/");
+    }
+
+    #[test]
+    fn wide_ranges() {
+        for label in [
+            5200,
+            4294972495,
+            4294972496,
+            281479271683151,
+            -4169,
+            -4294971464,
+            -4294971465,
+            -281479271682120,
+        ] {
+            // o rótulo seguinte confirma o tamanho em bits do padrão
+            let bytes = encode(&[(label, true), (1, true)]);
+            assert_eq!(
+                hierarchyid_to_string(&bytes).unwrap(),
+                format!("/{}/1/", label)
+            );
+        }
+
+        // seguido por `.` o rótulo é somado de 1 e o maior de uma faixa
+        // usa o padrão da faixa seguinte
+        for label in [4294972495, 4294972496, -4294971465, -281479271682120] {
+            let bytes = encode(&[(label, false), (3, true)]);
+            assert_eq!(
+                hierarchyid_to_string(&bytes).unwrap(),
+                format!("/{}.3/", label)
+            );
+        }
+    }
+
+    #[test]
+    fn wide_patterns_carry_48_bits() {
+        assert_eq!(PATTERNS[7].0.matches('x').count(), 48);
+        assert_eq!(PATTERNS[12].0.matches('x').count(), 48);
+    }
+
+    #[test]
+    fn truncated_value_is_an_error() {
+        assert!(hierarchyid_to_string(&[0x62]).is_err());
+    }
+}
@@ -1,108 +1,481 @@
-use anyhow::Ok;
+use anyhow::{Context, Ok, anyhow};
+use chrono::Local;
 use clap::Parser;
+use futures_util::FutureExt;
 use indicatif::{ProgressBar, ProgressStyle};
 use tiberius::{Query, QueryStream};
 
+mod browser;
+pub use browser::*;
+mod cast;
+pub use cast::*;
+mod connection_string;
+pub use connection_string::*;
 mod connections;
 pub use connections::*;
+mod params;
+pub use params::*;
+mod retry;
+pub use retry::*;
 mod schema_file;
 pub use schema_file::*;
+mod template;
+pub use template::*;
+mod credentials;
+pub use credentials::*;
+mod procedure;
+pub use procedure::*;
 mod converter;
 pub use converter::*;
+mod hierarchyid;
+pub use hierarchyid::*;
+mod spatial;
+pub use spatial::*;
 
+use std::collections::HashMap;
 use std::fs;
-use std::sync::Arc;
+use std::panic::AssertUnwindSafe;
+use std::time::Duration;
 
 /// Executa uma query no servidor e gera um arquivo parquet com o resultado
 #[derive(Parser)]
 struct Cli {
-    /// nome do servidor
+    /// nome do servidor (`host`, `host,porta`, `host:porta` ou `host\instancia`)
+    #[arg(short, long, required_unless_present_any = ["connection_string", "render_only"])]
+    name_server: Option<String>,
+    /// connection string no formato ADO.NET (`Server=...;Database=...;User Id=...`)
+    #[arg(short, long, conflicts_with = "name_server")]
+    connection_string: Option<String>,
+    /// banco de dados inicial da conexão
     #[arg(short, long)]
-    name_server: String,
+    database: Option<String>,
+    /// intenção da conexão (ReadOnly para réplicas secundárias Always On)
+    #[arg(long, value_enum)]
+    application_intent: Option<ApplicationIntent>,
+    /// modo de criptografia da conexão
+    #[arg(long, value_enum)]
+    encrypt: Option<EncryptMode>,
+    /// aceita o certificado do servidor sem validação
+    #[arg(long)]
+    trust_server_certificate: bool,
+    /// certificado da CA (pem, crt ou der) usado para validar o servidor
+    #[arg(long, conflicts_with = "trust_server_certificate")]
+    ca_certificate: Option<std::path::PathBuf>,
+    /// nome do host esperado no certificado do servidor
+    #[arg(long)]
+    host_name_in_certificate: Option<String>,
+    /// tempo limite em segundos para abrir a conexão TCP
+    #[arg(long, value_name = "SEGUNDOS")]
+    connect_timeout: Option<u64>,
+    /// tempo limite em segundos para o login no servidor
+    #[arg(long, value_name = "SEGUNDOS")]
+    login_timeout: Option<u64>,
+    /// novas tentativas de conexão em erros transitórios (padrão 3)
+    #[arg(long)]
+    retries: Option<u32>,
+    /// intervalo inicial em segundos entre tentativas, dobrado a cada falha
+    #[arg(long, value_name = "SEGUNDOS")]
+    retry_delay: Option<u64>,
+    /// instrução executada na sessão antes da consulta (pode repetir)
+    #[arg(long, value_name = "SQL")]
+    init_sql: Vec<String>,
+    /// arquivo com instruções de inicialização, lotes separados por `GO`
+    #[arg(long)]
+    init_file: Option<std::path::PathBuf>,
+    /// tempo limite em segundos da consulta e exportação, fechando a conexão
+    #[arg(long, value_name = "SEGUNDOS")]
+    query_timeout: Option<u64>,
     /// query a ser executada
     #[arg(short, long)]
     query: Option<String>,
+    /// exporta uma tabela inteira (`banco.schema.tabela`)
+    #[arg(
+        short,
+        long,
+        conflicts_with_all = ["query", "path_file", "parameters", "named_parameters", "params_file"]
+    )]
+    table: Option<String>,
     /// query a partir de um arquivo
     #[arg(short, long)]
     path_file: Option<std::path::PathBuf>,
+    /// executa uma procedure (`schema.procedure`) com os argumentos de --param
+    #[arg(long, conflicts_with_all = ["query", "path_file", "table", "render_only"])]
+    procedure: Option<String>,
+    /// parametro OUTPUT `nome=tipo` da procedure, como `total=int` (pode repetir)
+    #[arg(long = "output", value_name = "NOME=TIPO", requires = "procedure")]
+    outputs: Vec<String>,
     /// arquivo parquet de saída
     #[arg(short, long, default_value = "result_query.parquet")]
     file_parquet: String,
-    /// parametro de condicoes da consulta (opcional)
+    /// padrão dos nomes das colunas no parquet (padrão lower)
+    #[arg(long, value_enum)]
+    column_naming: Option<ColumnNaming>,
+    /// converte o tipo de uma coluna, `coluna=tipo` como `codigo=bigint` (pode repetir)
+    #[arg(long = "cast", value_name = "COLUNA=TIPO")]
+    casts: Vec<String>,
+    /// arquivo JSON ou TOML com as conversões `coluna = "tipo"`
+    #[arg(long)]
+    cast_file: Option<std::path::PathBuf>,
+    /// formato das colunas uniqueidentifier no parquet (padrão uuid)
+    #[arg(long, value_enum)]
+    guid_format: Option<GuidFormat>,
+    /// grava o fuso das colunas datetimeoffset em `<coluna>_offset`, em minutos
+    #[arg(long)]
+    offset_column: bool,
+    /// formato das colunas binary, varbinary e image no parquet (padrão raw)
+    #[arg(long, value_enum)]
+    binary_format: Option<BinaryFormat>,
+    /// parametro de condicoes da consulta (opcional), `tipo:valor` como `int:1290`,
+    /// `date:2024-01-31`, `decimal(18,2):10.50`, `bit:1` ou `null`
     parameters: Vec<String>,
-    /// nome do usuario
+    /// variavel `nome=valor` usada como `{{nome}}` no texto da consulta (pode repetir)
+    #[arg(long = "var", value_name = "NOME=VALOR")]
+    variables: Vec<String>,
+    /// apenas exibe a consulta após aplicar o template, sem executar
+    #[arg(long)]
+    render_only: bool,
+    /// parametro nomeado `nome=valor`, usado como `@nome` na consulta (pode repetir)
+    #[arg(long = "param", value_name = "NOME=VALOR")]
+    named_parameters: Vec<String>,
+    /// arquivo JSON ou TOML com parametros nomeados
+    #[arg(long)]
+    params_file: Option<std::path::PathBuf>,
+    /// nome do usuario (ou variável RUSTMSSQL_USER)
     #[arg(short, long)]
     user: Option<String>,
-    /// senha de acesso
+    /// senha de acesso (prefira --password-file, RUSTMSSQL_PASSWORD ou o prompt)
     #[arg(short, long)]
     secret: Option<String>,
+    /// arquivo com a senha de acesso (permissão 600 no unix)
+    #[arg(long, conflicts_with = "secret")]
+    password_file: Option<std::path::PathBuf>,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli: Cli = Cli::parse();
 
+    let mut query: String = String::new();
+
+    if let Some(str_query) = &cli.query {
+        query = str_query.clone();
+    } else if let Some(file_query) = &cli.path_file {
+        query = fs::read_to_string(file_query)?;
+    };
+
+    let mut variables: HashMap<String, String> = HashMap::new();
+    for variable in &cli.variables {
+        let (name, value) = variable
+            .split_once('=')
+            .with_context(|| format!("Variável {} deve estar no formato nome=valor", variable))?;
+        variables.insert(name.trim().to_string(), value.to_string());
+    }
+    query = render_template(&query, &variables, Local::now().date_naive())?;
+
+    if cli.render_only {
+        println!("{}", query);
+        return Ok(());
+    }
+
+    let mut options = match (&cli.connection_string, &cli.name_server) {
+        (Some(connection_string), _) => parse_connection_string(connection_string)?,
+        (None, Some(name_server)) => ConnectionOptions::new(name_server),
+        (None, None) => unreachable!(),
+    };
+
+    // usuario e senha da linha de comando tem prioridade
+    if cli.user.is_some() {
+        options.user = cli.user;
+    }
+    if cli.secret.is_some() {
+        options.password = cli.secret;
+    }
+    if cli.database.is_some() {
+        options.database = cli.database;
+    }
+    if let Some(application_intent) = cli.application_intent {
+        options.application_intent = application_intent;
+    }
+    if let Some(encrypt) = cli.encrypt {
+        options.encrypt = encrypt;
+    }
+    if cli.trust_server_certificate {
+        options.trust_server_certificate = true;
+    }
+    if cli.ca_certificate.is_some() {
+        options.ca_certificate = cli.ca_certificate;
+    }
+    if cli.host_name_in_certificate.is_some() {
+        options.host_name_in_certificate = cli.host_name_in_certificate;
+    }
+    if let Some(seconds) = cli.connect_timeout {
+        options.connect_timeout = Duration::from_secs(seconds);
+    }
+    if let Some(seconds) = cli.login_timeout {
+        options.login_timeout = Duration::from_secs(seconds);
+    }
+    if let Some(retries) = cli.retries {
+        options.retry.max_retries = retries;
+    }
+    if let Some(seconds) = cli.retry_delay {
+        options.retry.initial_delay = Duration::from_secs(seconds);
+    }
+
+    resolve_credentials(&mut options, cli.password_file.as_deref())?;
+
     println!("{}", "=*".repeat(30));
-    println!("Servidor: {}", cli.name_server);
+    println!("Servidor: {}", options.server);
+    if let Some(database) = &options.database {
+        println!("Banco de dados: {}", database);
+    }
+    match &options.user {
+        Some(user) => println!("Usuário: {}", user),
+        None => println!("Usuário: autenticação integrada"),
+    }
     println!("Saida parquet: {}", cli.file_parquet);
 
-    let mut query: String = String::new();
-
-    if let Some(str_query) = cli.query {
+    if let Some(procedure) = &cli.procedure {
+        println!("\n=> Procedure {} ...\n", procedure);
+    } else if cli.query.is_some() {
         println!("\n=> Query importada ! ...\n");
-        query = str_query;
-    } else if let Some(file_query) = cli.path_file {
-        query = fs::read_to_string(&file_query)?;
+    } else if cli.path_file.is_some() {
         println!("\n=> Arquivo importado ! ...\n");
     };
 
-    let schema_sql: Vec<MSchema> = schema_mssql_query(
-        query.as_str(),
-        cli.name_server.as_str(),
-        cli.user.as_deref(),
-        cli.secret.as_deref(),
-    )
-    .await?;
-    let schema = create_schema_parquet(&schema_sql);
-
-    let mut client = connect_server(
-        cli.name_server.as_str(),
-        cli.user.as_deref(),
-        cli.secret.as_deref(),
-    )
-    .await?;
+    let mut params: Vec<SqlParam> = cli
+        .parameters
+        .iter()
+        .map(|p| SqlParam::parse(p))
+        .collect::<anyhow::Result<_>>()?;
+
+    // parametros nomeados viram posicionais apos os informados por posicao
+    let mut named_params: Vec<(String, SqlParam)> = match &cli.params_file {
+        Some(params_file) => read_params_file(params_file)?,
+        None => Vec::new(),
+    };
+    for param in &cli.named_parameters {
+        named_params.push(parse_named_param(param)?);
+    }
+
+    let mut init_statements: Vec<String> = cli.init_sql;
+    if let Some(init_file) = cli.init_file {
+        init_statements.extend(split_batches(&fs::read_to_string(&init_file)?)?);
+    }
+
+    if let Some(procedure) = &cli.procedure {
+        // os argumentos nomeados sao passados como `@nome = @Pn`
+        let outputs: Vec<OutputParam> = cli
+            .outputs
+            .iter()
+            .map(|o| OutputParam::parse(o))
+            .collect::<anyhow::Result<_>>()?;
+        // valores de --param prevalecem sobre os do arquivo
+        let (names, named): (Vec<String>, Vec<SqlParam>) =
+            dedupe_named_params(named_params).into_iter().unzip();
+
+        query = procedure_sql(
+            &ObjectName::parse(procedure)?,
+            params.len(),
+            &names,
+            &outputs,
+        )?;
+        params.extend(named);
+        init_statements.push(output_table_sql());
+    } else if !named_params.is_empty() {
+        let (rewritten, named) = bind_named_params(&query, named_params, params.len() + 1);
+        query = rewritten;
+        params.extend(named);
+    }
+
+    // uma unica sessao para o schema e a exportacao
+    let mut client = connect_server(&options).await?;
+    run_init_statements(&mut client, &init_statements).await?;
+
+    let schema_sql: Vec<MSchema> = if let Some(table_name) = &cli.table {
+        let table = ObjectName::parse(table_name)?;
+        let database = match &table.database {
+            Some(database) => database.clone(),
+            None => current_database(&mut client).await?,
+        };
+
+        let schema_sql =
+            schema_mssql(&mut client, &database, table.schema.as_deref(), &table.name).await?;
+        if schema_sql.is_empty() {
+            anyhow::bail!("Tabela {} não encontrada", table_name);
+        }
+
+        query = select_table_sql(&table, &schema_sql);
+        let schema_sql = variant_type_columns(schema_sql);
+        println!(
+            "=> Tabela {} com {} colunas\n",
+            table_name,
+            schema_sql.len()
+        );
+        schema_sql
+    } else if cli.procedure.is_some() {
+        // o schema de cada conjunto vem dos metadados do resultado
+        Vec::new()
+    } else {
+        let param_types: Vec<String> = params.iter().map(|p| p.sql_type.clone()).collect();
+        match schema_mssql_query(&mut client, query.as_str(), &param_types).await {
+            Err(error) => {
+                // tabelas temporarias, SQL dinamico e algumas procedures nao sao descritos
+                eprintln!(
+                    "Consulta não descrita pelo sp_describe_first_result_set ({:#}), \
+                     o schema será montado com os metadados do resultado",
+                    error
+                );
+                Vec::new()
+            }
+            schema_sql => {
+                let schema_sql = schema_sql?;
+
+                // colunas que o tiberius nao decodifica e money sao convertidas no servidor,
+                // desde que a consulta envolvida tambem possa ser descrita
+                let wrapped = match wrap_query_sql(&query, &schema_sql) {
+                    Some(wrapped) => schema_mssql_query(&mut client, &wrapped, &param_types)
+                        .await
+                        .is_ok()
+                        .then_some(wrapped),
+                    None => None,
+                };
+
+                match wrapped {
+                    Some(wrapped) => {
+                        query = wrapped;
+                        variant_type_columns(schema_sql)
+                    }
+                    None => {
+                        check_driver_types(&schema_sql)?;
+                        schema_sql
+                    }
+                }
+            }
+        }
+    };
+
+    // conversoes da linha de comando prevalecem sobre as do arquivo
+    let mut casts: Vec<ColumnCast> = match &cli.cast_file {
+        Some(cast_file) => read_cast_file(cast_file)?,
+        None => Vec::new(),
+    };
+    for cast in &cli.casts {
+        casts.push(ColumnCast::parse(cast)?);
+    }
+
+    let export_options = ExportOptions {
+        column_naming: cli.column_naming.unwrap_or_default(),
+        casts,
+        guid_format: cli.guid_format.unwrap_or_default(),
+        offset_column: cli.offset_column,
+        binary_format: cli.binary_format.unwrap_or_default(),
+    };
 
     let mut select: Query<'_> = Query::new(query);
-    for param in cli.parameters {
+    for param in params {
         select.bind(param);
     }
 
-    // INICIAR A BARRA DE PROGRESSO AQUI
-
     // Barra de progresso
     let progress = start_progress()?;
     progress.set_message("Consultando ...");
 
-    let stream: QueryStream<'_> = select.query(&mut client).await?;
+    // arquivos criados por esta execucao, removidos se a exportacao falhar
+    let mut files: Vec<String> = Vec::new();
 
-    progress.finish_with_message("Consulta Finalizada ... ✅");
+    let export = async {
+        let stream: QueryStream<'_> = select.query(&mut client).await?;
 
-    let progress = start_progress()?;
+        progress.finish_with_message("Consulta Finalizada ... ✅");
+
+        let progress = start_progress()?;
+
+        write_parquet_from_stream(
+            stream,
+            &schema_sql,
+            &export_options,
+            cli.file_parquet.as_str(),
+            &progress,
+            &mut files,
+        )
+        .await
+    };
 
-    write_parquet_from_stream(
-        stream,
-        Arc::new(schema),
-        &schema_sql,
-        cli.file_parquet.as_str(),
-        &progress,
-    )
-    .await?;
+    // o tiberius interrompe com panic nas colunas que nao decodifica, como as
+    // de uma consulta nao descrita ou dos conjuntos de resultados seguintes
+    let export = AssertUnwindSafe(export).catch_unwind();
+
+    let query_timeout = cli.query_timeout.map(Duration::from_secs);
+
+    let result = tokio::select! {
+        result = export => result.unwrap_or_else(|_| Err(anyhow!(
+            "O driver não decodifica uma coluna do resultado: converta colunas hierarchyid, \
+             geography, geometry, sql_variant e tipos CLR no SQL, como \
+             CAST(coluna AS varbinary(max)) com --cast, ou exporte com --table"
+        ))),
+        _ = wait_timeout(query_timeout) => Err(anyhow!(
+            "Tempo limite da consulta excedido ({}s)",
+            query_timeout.unwrap_or_default().as_secs()
+        )),
+        _ = tokio::signal::ctrl_c() => Err(anyhow!("Exportação cancelada pelo usuário")),
+    };
+
+    if let Err(error) = result {
+        // o driver nao envia o attention do TDS, fechar a conexao so interrompe
+        // o servidor quando ele tentar enviar mais registros
+        drop(client);
+        remove_partial_files(&files)?;
+        return Err(error);
+    }
+
+    if files.is_empty() {
+        println!("=> Nenhum conjunto de resultados retornado");
+    } else if files.len() > 1 {
+        println!("=> {} conjuntos de resultados exportados:", files.len());
+        for file in &files {
+            println!("   {}", file);
+        }
+    }
+
+    if cli.procedure.is_some() {
+        for (name, value) in procedure_outputs(&mut client).await? {
+            let value = value.as_deref().unwrap_or("NULL");
+            if name == RETURN_VALUE {
+                println!("=> Valor de retorno: {}", value);
+            } else {
+                println!("=> Parâmetro de saída @{}: {}", name, value);
+            }
+        }
+    }
 
     println!("{}", "=*".repeat(30));
 
     Ok(())
 }
 
+fn remove_partial_files(files: &[String]) -> anyhow::Result<()> {
+    //! Remove os arquivos criados pela exportação que falhou,
+    //! arquivos de execuções anteriores são mantidos.
+
+    for file in files {
+        if std::path::Path::new(file).exists() {
+            fs::remove_file(file)?;
+            eprintln!("Arquivo parcial {} removido", file);
+        }
+    }
+
+    Ok(())
+}
+
+async fn wait_timeout(timeout: Option<Duration>) {
+    //! Aguarda o tempo limite, sem tempo limite nunca termina.
+
+    match timeout {
+        Some(timeout) => tokio::time::sleep(timeout).await,
+        None => std::future::pending().await,
+    }
+}
+
 fn start_progress() -> anyhow::Result<ProgressBar> {
     let progress = ProgressBar::new_spinner();
     progress.set_style(
@@ -121,3 +494,46 @@ fn start_progress() -> anyhow::Result<ProgressBar> {
     progress.enable_steady_tick(std::time::Duration::from_millis(80));
     Ok(progress)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_does_not_accept_query_parameters() {
+        let parse = |args: &[&str]| {
+            Cli::try_parse_from(
+                ["rustmssql", "-n", "localhost", "-t", "dbo.pedidos"]
+                    .iter()
+                    .chain(args),
+            )
+        };
+
+        assert!(parse(&[]).is_ok());
+        assert!(parse(&["int:1"]).is_err());
+        assert!(parse(&["--param", "id=1"]).is_err());
+        assert!(parse(&["--params-file", "parametros.toml"]).is_err());
+    }
+
+    #[test]
+    fn only_files_of_this_run_are_removed() {
+        let dir = std::env::temp_dir().join(format!("rustmssql_partial_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let previous = dir.join("saida.parquet");
+        let partial = dir.join("saida_1.parquet");
+        fs::write(&previous, b"anterior").unwrap();
+        fs::write(&partial, b"parcial").unwrap();
+
+        let files = vec![
+            partial.display().to_string(),
+            dir.join("saida_2.parquet").display().to_string(),
+        ];
+        remove_partial_files(&files).unwrap();
+
+        assert!(previous.exists());
+        assert!(!partial.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
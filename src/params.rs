@@ -0,0 +1,740 @@
+use anyhow::{Context, Ok, bail};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use std::borrow::Cow;
+use std::{fs, path::Path};
+use tiberius::numeric::Numeric;
+use tiberius::time::{Date, DateTime, DateTime2, Time};
+use tiberius::{ColumnData, IntoSql};
+
+/// Parâmetro da consulta com o tipo declarado no SQL Server.
+/// Informado como `tipo:valor`, por exemplo `int:1290`,
+/// `date:2024-01-31`, `decimal(18,2):10.50` ou `null`.
+#[derive(Debug, Clone)]
+pub struct SqlParam {
+    pub sql_type: String,
+    pub value: ColumnData<'static>,
+}
+
+impl<'a> IntoSql<'a> for SqlParam {
+    fn into_sql(self) -> ColumnData<'a> {
+        self.value
+    }
+}
+
+impl SqlParam {
+    pub fn parse(param: &str) -> anyhow::Result<Self> {
+        //! Interpreta um parâmetro da linha de comando.
+        //! Sem um prefixo de tipo conhecido o valor é enviado como `nvarchar`,
+        //! `null` sozinho envia um `nvarchar` nulo.
+
+        if param.eq_ignore_ascii_case("null") {
+            return Ok(Self::string(None));
+        }
+
+        let Some((prefix, value)) = param.split_once(':') else {
+            return Ok(Self::string(Some(param)));
+        };
+
+        let prefix = prefix.trim().to_lowercase();
+        let (type_name, args) = match prefix.split_once('(') {
+            Some((name, args)) => (name.trim(), Some(args.trim_end_matches(')'))),
+            None => (prefix.as_str(), None),
+        };
+
+        if !is_known_type(type_name) {
+            return Ok(Self::string(Some(param)));
+        }
+
+        let is_null = value.eq_ignore_ascii_case("null");
+
+        Self::typed(type_name, args, value, is_null)
+            .with_context(|| format!("Parâmetro inválido: {}", param))
+    }
+
+    fn string(value: Option<&str>) -> Self {
+        Self {
+            sql_type: "nvarchar(4000)".to_string(),
+            value: ColumnData::String(value.map(|v| Cow::Owned(v.to_string()))),
+        }
+    }
+
+    fn typed(
+        type_name: &str,
+        args: Option<&str>,
+        value: &str,
+        is_null: bool,
+    ) -> anyhow::Result<Self> {
+        let value = value.trim();
+
+        let (sql_type, data) = match type_name {
+            "tinyint" => (
+                "tinyint".to_string(),
+                ColumnData::U8((!is_null).then(|| value.parse()).transpose()?),
+            ),
+            "smallint" => (
+                "smallint".to_string(),
+                ColumnData::I16((!is_null).then(|| value.parse()).transpose()?),
+            ),
+            "int" => (
+                "int".to_string(),
+                ColumnData::I32((!is_null).then(|| value.parse()).transpose()?),
+            ),
+            "bigint" => (
+                "bigint".to_string(),
+                ColumnData::I64((!is_null).then(|| value.parse()).transpose()?),
+            ),
+            "bit" => (
+                "bit".to_string(),
+                ColumnData::Bit((!is_null).then(|| parse_bit(value)).transpose()?),
+            ),
+            "real" => (
+                "real".to_string(),
+                ColumnData::F32((!is_null).then(|| value.parse()).transpose()?),
+            ),
+            "float" => (
+                "float".to_string(),
+                ColumnData::F64((!is_null).then(|| value.parse()).transpose()?),
+            ),
+            "decimal" | "numeric" => {
+                let (precision, scale) = parse_precision_scale(args)?;
+                let numeric = (!is_null)
+                    .then(|| parse_decimal(value, precision, scale))
+                    .transpose()?;
+                (
+                    format!("{}({},{})", type_name, precision, scale),
+                    ColumnData::Numeric(numeric),
+                )
+            }
+            "date" => (
+                "date".to_string(),
+                ColumnData::Date(
+                    (!is_null)
+                        .then(|| parse_date(value).map(to_sql_date))
+                        .transpose()?,
+                ),
+            ),
+            "datetime" => (
+                "datetime".to_string(),
+                ColumnData::DateTime(
+                    (!is_null)
+                        .then(|| parse_datetime(value).map(to_sql_datetime))
+                        .transpose()?,
+                ),
+            ),
+            "datetime2" => (
+                "datetime2".to_string(),
+                ColumnData::DateTime2(
+                    (!is_null)
+                        .then(|| {
+                            parse_datetime(value).map(|dt| {
+                                DateTime2::new(to_sql_date(dt.date()), to_sql_time(dt.time()))
+                            })
+                        })
+                        .transpose()?,
+                ),
+            ),
+            "time" => (
+                "time".to_string(),
+                ColumnData::Time(
+                    (!is_null)
+                        .then(|| parse_time(value).map(to_sql_time))
+                        .transpose()?,
+                ),
+            ),
+            // strings sempre sao enviadas como nvarchar
+            _ => return Ok(Self::string((!is_null).then_some(value))),
+        };
+
+        Ok(Self {
+            sql_type,
+            value: data,
+        })
+    }
+}
+
+pub fn parse_named_param(param: &str) -> anyhow::Result<(String, SqlParam)> {
+    //! Interpreta `nome=valor` de `--param`, o valor aceita a sintaxe `tipo:valor`.
+
+    let (name, value) = param
+        .split_once('=')
+        .with_context(|| format!("Parâmetro {} deve estar no formato nome=valor", param))?;
+
+    Ok((normalize_name(name)?, SqlParam::parse(value)?))
+}
+
+pub fn declared_type(sql_type: &str) -> anyhow::Result<String> {
+    //! Valida um tipo aceito nos parâmetros e retorna a sua declaração,
+    //! como `decimal(18,2)`, ou `nvarchar(4000)` para `str`.
+
+    let sql_type = sql_type.trim().to_lowercase();
+    let (type_name, args) = match sql_type.split_once('(') {
+        Some((name, args)) => (name.trim(), Some(args.trim_end_matches(')'))),
+        None => (sql_type.as_str(), None),
+    };
+
+    if !is_known_type(type_name) {
+        bail!("Tipo de parâmetro não suportado: {}", sql_type);
+    }
+
+    Ok(SqlParam::typed(type_name, args, "null", true)?.sql_type)
+}
+
+pub fn read_params_file(path: &Path) -> anyhow::Result<Vec<(String, SqlParam)>> {
+    //! Lê os parâmetros nomeados de um arquivo JSON ou TOML,
+    //! um objeto com `nome = valor` no nível principal.
+    //! Textos aceitam a sintaxe `tipo:valor`, números inteiros viram `int`
+    //! ou `bigint`, decimais `float`, booleanos `bit` e datas do TOML `date`
+    //! ou `datetime2`.
+
+    let values: Vec<(String, String)> = read_object_file(path, "parâmetros")?
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                FileValue::Json(value) => json_to_param(value)?,
+                FileValue::Toml(value) => toml_to_param(value)?,
+            };
+            Ok((name, value))
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    values
+        .into_iter()
+        .map(|(name, value)| {
+            let param = SqlParam::parse(&value)
+                .with_context(|| format!("Parâmetro {} do arquivo", name))?;
+            Ok((normalize_name(&name)?, param))
+        })
+        .collect()
+}
+
+/// Valor de um arquivo JSON ou TOML lido por `read_object_file`.
+pub enum FileValue {
+    Json(serde_json::Value),
+    Toml(toml::Value),
+}
+
+impl FileValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            FileValue::Json(value) => value.as_str(),
+            FileValue::Toml(value) => value.as_str(),
+        }
+    }
+}
+
+pub fn read_object_file(
+    path: &Path,
+    description: &str,
+) -> anyhow::Result<Vec<(String, FileValue)>> {
+    //! Lê um arquivo JSON ou TOML com um objeto `nome = valor` no nível principal,
+    //! usado pelos arquivos de parâmetros e de conversões.
+    //! `description` identifica o arquivo nas mensagens de erro.
+
+    let content = fs::read_to_string(path).with_context(|| {
+        format!(
+            "Falha ao ler o arquivo de {} {}",
+            description,
+            path.display()
+        )
+    })?;
+
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    Ok(match extension.as_str() {
+        "json" => {
+            let json: serde_json::Value = serde_json::from_str(&content)?;
+            let serde_json::Value::Object(map) = json else {
+                bail!("Arquivo {} deve conter um objeto JSON", path.display());
+            };
+            map.into_iter()
+                .map(|(name, value)| (name, FileValue::Json(value)))
+                .collect()
+        }
+        "toml" => {
+            let table: toml::Table = content.parse()?;
+            table
+                .into_iter()
+                .map(|(name, value)| (name, FileValue::Toml(value)))
+                .collect()
+        }
+        _ => bail!(
+            "Arquivo de {} {} deve ter extensão json ou toml",
+            description,
+            path.display()
+        ),
+    })
+}
+
+fn json_to_param(value: serde_json::Value) -> anyhow::Result<String> {
+    Ok(match value {
+        serde_json::Value::Null => "null".to_string(),
+        serde_json::Value::Bool(b) => format!("bit:{}", b),
+        serde_json::Value::Number(n) => number_to_param(n.as_i64(), &n.to_string()),
+        serde_json::Value::String(s) => s,
+        _ => bail!("Valor {} não suportado como parâmetro", value),
+    })
+}
+
+fn toml_to_param(value: toml::Value) -> anyhow::Result<String> {
+    Ok(match value {
+        toml::Value::Boolean(b) => format!("bit:{}", b),
+        toml::Value::Integer(i) => number_to_param(Some(i), &i.to_string()),
+        toml::Value::Float(f) => format!("float:{}", f),
+        toml::Value::String(s) => s,
+        toml::Value::Datetime(dt) if dt.time.is_none() => format!("date:{}", dt),
+        toml::Value::Datetime(dt) if dt.date.is_some() && dt.offset.is_none() => {
+            format!("datetime2:{}", dt)
+        }
+        _ => bail!("Valor {} não suportado como parâmetro", value),
+    })
+}
+
+fn number_to_param(integer: Option<i64>, text: &str) -> String {
+    match integer {
+        Some(i) if i32::try_from(i).is_ok() => format!("int:{}", i),
+        Some(i) => format!("bigint:{}", i),
+        None => format!("float:{}", text),
+    }
+}
+
+pub fn normalize_name(name: &str) -> anyhow::Result<String> {
+    //! Remove o `@` inicial e mantém a grafia do nome, usada nos argumentos
+    //! da procedure. Nomes são comparados sem diferenciar maiúsculas
+    //! de minúsculas.
+
+    let name = name.trim();
+    let name = name.strip_prefix('@').unwrap_or(name);
+
+    if name.is_empty() || !name.chars().all(is_variable_char) {
+        bail!("Nome de parâmetro inválido: {}", name);
+    }
+
+    Ok(name.to_string())
+}
+
+pub fn dedupe_named_params(named: Vec<(String, SqlParam)>) -> Vec<(String, SqlParam)> {
+    //! Um nome repetido prevalece sobre o anterior, com a grafia
+    //! e a posição do último informado.
+
+    let mut values: Vec<(String, SqlParam)> = Vec::new();
+    for (name, param) in named {
+        values.retain(|(n, _)| n.to_lowercase() != name.to_lowercase());
+        values.push((name, param));
+    }
+
+    values
+}
+
+fn is_variable_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '#' | '$' | '@')
+}
+
+pub fn bind_named_params(
+    query: &str,
+    named: Vec<(String, SqlParam)>,
+    first_position: usize,
+) -> (String, Vec<SqlParam>) {
+    //! Reescreve `@nome` na consulta para `@Pn`, a partir de `first_position`,
+    //! e retorna os parâmetros na ordem das posições.
+    //! Ignora textos, comentários, identificadores entre colchetes e variáveis
+    //! não informadas, como as declaradas com `DECLARE` na própria consulta.
+    //! Um nome repetido em `named` prevalece sobre o anterior.
+
+    let mut values: Vec<(String, SqlParam)> = dedupe_named_params(named)
+        .into_iter()
+        .map(|(name, param)| (name.to_lowercase(), param))
+        .collect();
+
+    let mut positions: Vec<String> = Vec::new();
+    let mut rewritten = String::with_capacity(query.len());
+    let mut chars = query.chars().peekable();
+    let mut previous: Option<char> = None;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '[' | '"' => {
+                // copia textos e identificadores delimitados
+                let close = if c == '[' { ']' } else { c };
+                rewritten.push(c);
+                while let Some(next) = chars.next() {
+                    rewritten.push(next);
+                    if next == close {
+                        if chars.peek() == Some(&close) {
+                            rewritten.push(chars.next().unwrap_or(close));
+                        } else {
+                            break;
+                        }
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                rewritten.push(c);
+                for next in chars.by_ref() {
+                    rewritten.push(next);
+                    if next == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                rewritten.push(c);
+                rewritten.push(chars.next().unwrap_or('*'));
+                let mut depth = 1;
+                while depth > 0 {
+                    let Some(next) = chars.next() else { break };
+                    rewritten.push(next);
+                    match (next, chars.peek()) {
+                        ('*', Some('/')) => {
+                            rewritten.push(chars.next().unwrap_or('/'));
+                            depth -= 1;
+                        }
+                        ('/', Some('*')) => {
+                            rewritten.push(chars.next().unwrap_or('*'));
+                            depth += 1;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            '@' if previous != Some('@') && chars.peek() != Some(&'@') => {
+                let mut name = String::new();
+                while let Some(next) = chars.next_if(|n| is_variable_char(*n) && *n != '@') {
+                    name.push(next);
+                }
+
+                let key = name.to_lowercase();
+                if values.iter().any(|(n, _)| *n == key) {
+                    let position = match positions.iter().position(|p| *p == key) {
+                        Some(position) => position,
+                        None => {
+                            positions.push(key);
+                            positions.len() - 1
+                        }
+                    };
+                    rewritten.push_str(&format!("@P{}", first_position + position));
+                } else {
+                    rewritten.push('@');
+                    rewritten.push_str(&name);
+                }
+                previous = name.chars().last().or(Some('@'));
+                continue;
+            }
+            _ => rewritten.push(c),
+        }
+        previous = Some(c);
+    }
+
+    let params = positions
+        .into_iter()
+        .filter_map(|name| {
+            let index = values.iter().position(|(n, _)| *n == name)?;
+            Some(values.swap_remove(index).1)
+        })
+        .collect();
+
+    (rewritten, params)
+}
+
+fn is_known_type(type_name: &str) -> bool {
+    matches!(
+        type_name,
+        "tinyint"
+            | "smallint"
+            | "int"
+            | "bigint"
+            | "bit"
+            | "real"
+            | "float"
+            | "decimal"
+            | "numeric"
+            | "date"
+            | "datetime"
+            | "datetime2"
+            | "time"
+            | "nvarchar"
+            | "str"
+    )
+}
+
+pub fn parse_bit(value: &str) -> anyhow::Result<bool> {
+    match value.to_lowercase().as_str() {
+        "1" | "true" => Ok(true),
+        "0" | "false" => Ok(false),
+        _ => bail!("Valor bit inválido: {}", value),
+    }
+}
+
+pub fn parse_precision_scale(args: Option<&str>) -> anyhow::Result<(u8, u8)> {
+    //! Lê `(precisao,escala)`, o padrão do SQL Server é `(18,0)`.
+
+    let Some(args) = args else {
+        return Ok((18, 0));
+    };
+
+    let (precision, scale) = match args.split_once(',') {
+        Some((precision, scale)) => (precision.trim().parse()?, scale.trim().parse()?),
+        None => (args.trim().parse()?, 0),
+    };
+
+    if !(1..=38).contains(&precision) || scale > precision {
+        bail!("Precisão ({},{}) inválida", precision, scale);
+    }
+
+    Ok((precision, scale))
+}
+
+pub fn parse_decimal(value: &str, precision: u8, scale: u8) -> anyhow::Result<Numeric> {
+    //! Converte o texto em um `Numeric` sem passar por ponto flutuante.
+
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+
+    if integer.is_empty() && fraction.is_empty()
+        || !integer
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        bail!("Valor decimal inválido: {}", value);
+    }
+    if fraction.len() > scale as usize {
+        bail!("Valor {} excede a escala {}", value, scale);
+    }
+
+    let integer = integer.trim_start_matches('0');
+    if integer.len() > (precision - scale) as usize {
+        bail!("Valor {} excede a precisão {}", value, precision);
+    }
+
+    let scaled = format!("{}{:0<width$}", integer, fraction, width = scale as usize);
+    let mut scaled: i128 = if scaled.is_empty() {
+        0
+    } else {
+        scaled.parse()?
+    };
+    if negative {
+        scaled = -scaled;
+    }
+
+    Ok(Numeric::new_with_scale(scaled, scale))
+}
+
+pub fn parse_date(value: &str) -> anyhow::Result<NaiveDate> {
+    Ok(NaiveDate::parse_from_str(value, "%Y-%m-%d")?)
+}
+
+pub fn parse_time(value: &str) -> anyhow::Result<NaiveTime> {
+    Ok(NaiveTime::parse_from_str(value, "%H:%M:%S%.f")
+        .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M"))?)
+}
+
+pub fn parse_datetime(value: &str) -> anyhow::Result<NaiveDateTime> {
+    //! Aceita `AAAA-MM-DD`, `AAAA-MM-DD HH:MM:SS[.fff]` e o separador `T`.
+
+    for format in [
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+    ] {
+        if let std::result::Result::Ok(datetime) = NaiveDateTime::parse_from_str(value, format) {
+            return Ok(datetime);
+        }
+    }
+
+    Ok(parse_date(value)?.and_time(NaiveTime::MIN))
+}
+
+fn to_sql_date(date: NaiveDate) -> Date {
+    //! Dias desde 0001-01-01, base do tipo `date`.
+
+    let base = NaiveDate::from_ymd_opt(1, 1, 1).unwrap_or_default();
+    Date::new(date.signed_duration_since(base).num_days() as u32)
+}
+
+fn to_sql_time(time: NaiveTime) -> Time {
+    //! Incrementos de 100 nanossegundos, escala 7.
+
+    let nanos = time.num_seconds_from_midnight() as u64 * 1_000_000_000 + time.nanosecond() as u64;
+    Time::new(nanos / 100, 7)
+}
+
+fn to_sql_datetime(datetime: NaiveDateTime) -> DateTime {
+    //! Dias desde 1900-01-01 e frações de 1/300 de segundo, base do tipo `datetime`.
+
+    let base = NaiveDate::from_ymd_opt(1900, 1, 1).unwrap_or_default();
+    let days = datetime.date().signed_duration_since(base).num_days() as i32;
+
+    let time = datetime.time();
+    let nanos = time.num_seconds_from_midnight() as u64 * 1_000_000_000 + time.nanosecond() as u64;
+    let fragments = ((nanos * 300 + 500_000_000) / 1_000_000_000).min(300 * 86_400 - 1);
+
+    DateTime::new(days, fragments as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bind(query: &str, names: &[&str]) -> (String, Vec<String>) {
+        //! Retorna a consulta reescrita e os valores na ordem das posições.
+
+        let named = names
+            .iter()
+            .map(|name| (name.to_string(), SqlParam::parse(name).unwrap()))
+            .collect();
+        let (rewritten, params) = bind_named_params(query, named, 1);
+
+        let values = params
+            .into_iter()
+            .map(|param| match param.value {
+                ColumnData::String(Some(value)) => value.to_string(),
+                value => format!("{:?}", value),
+            })
+            .collect();
+
+        (rewritten, values)
+    }
+
+    #[test]
+    fn names_are_numbered_in_order_of_use() {
+        let (sql, values) = bind(
+            "SELECT * FROM t WHERE b = @B AND a = @a OR b2 = @b",
+            &["a", "b"],
+        );
+
+        assert_eq!(sql, "SELECT * FROM t WHERE b = @P1 AND a = @P2 OR b2 = @P1");
+        assert_eq!(values, vec!["b", "a"]);
+    }
+
+    #[test]
+    fn strings_identifiers_and_comments_are_kept() {
+        let query = "SELECT '@a', 'it''s @a', [@a], [x]]@a], \"@a\" -- @a\n\
+                     /* @a /* @a */ @a */ FROM t WHERE c = @a";
+        let (sql, values) = bind(query, &["a"]);
+
+        assert_eq!(
+            sql,
+            "SELECT '@a', 'it''s @a', [@a], [x]]@a], \"@a\" -- @a\n\
+             /* @a /* @a */ @a */ FROM t WHERE c = @P1"
+        );
+        assert_eq!(values, vec!["a"]);
+    }
+
+    #[test]
+    fn globals_and_unknown_variables_are_kept() {
+        let (sql, values) = bind(
+            "DECLARE @total int = @@ROWCOUNT; SELECT @total, @a, @ab",
+            &["a"],
+        );
+
+        assert_eq!(
+            sql,
+            "DECLARE @total int = @@ROWCOUNT; SELECT @total, @P1, @ab"
+        );
+        assert_eq!(values, vec!["a"]);
+    }
+
+    #[test]
+    fn repeated_names_keep_the_last_value() {
+        let named = vec![
+            ("Total".to_string(), SqlParam::parse("int:1").unwrap()),
+            ("loja".to_string(), SqlParam::parse("int:2").unwrap()),
+            ("total".to_string(), SqlParam::parse("int:3").unwrap()),
+        ];
+        let names: Vec<String> = dedupe_named_params(named)
+            .into_iter()
+            .map(|(name, param)| format!("{}={:?}", name, param.value))
+            .collect();
+
+        assert_eq!(names, vec!["loja=I32(Some(2))", "total=I32(Some(3))"]);
+    }
+
+    #[test]
+    fn names_keep_their_spelling() {
+        assert_eq!(normalize_name(" @DataInicio ").unwrap(), "DataInicio");
+        assert!(normalize_name("@").is_err());
+        assert!(normalize_name("a b").is_err());
+    }
+
+    #[test]
+    fn unused_names_are_not_bound() {
+        let (sql, values) = bind("SELECT 1", &["a"]);
+
+        assert_eq!(sql, "SELECT 1");
+        assert!(values.is_empty());
+    }
+
+    #[test]
+    fn decimal_values_respect_precision_and_scale() {
+        let param = SqlParam::parse("decimal(6,2):-1234.5").unwrap();
+        assert_eq!(param.sql_type, "decimal(6,2)");
+        let ColumnData::Numeric(Some(n)) = param.value else {
+            panic!("esperado um decimal");
+        };
+        assert_eq!((n.value(), n.scale()), (-123450, 2));
+
+        assert_eq!(
+            SqlParam::parse("numeric:10").unwrap().sql_type,
+            "numeric(18,0)"
+        );
+        // mais casas que a escala, mais dígitos inteiros que a precisão
+        assert!(SqlParam::parse("decimal(6,2):1.234").is_err());
+        assert!(SqlParam::parse("decimal(6,2):12345.6").is_err());
+        assert!(SqlParam::parse("decimal(6,2):1e3").is_err());
+        assert!(SqlParam::parse("decimal(40,2):1").is_err());
+        assert!(SqlParam::parse("decimal(4,6):1").is_err());
+    }
+
+    #[test]
+    fn unknown_prefixes_are_sent_as_text() {
+        let param = SqlParam::parse("http://servidor").unwrap();
+        assert_eq!(param.sql_type, "nvarchar(4000)");
+        assert!(matches!(param.value, ColumnData::String(Some(v)) if v == "http://servidor"));
+
+        assert!(matches!(
+            SqlParam::parse("NULL").unwrap().value,
+            ColumnData::String(None)
+        ));
+        assert!(matches!(
+            SqlParam::parse("int:null").unwrap().value,
+            ColumnData::I32(None)
+        ));
+        assert!(SqlParam::parse("int:abc").is_err());
+        assert!(SqlParam::parse("tinyint:300").is_err());
+        assert!(SqlParam::parse("bit:sim").is_err());
+        assert!(declared_type("xml").is_err());
+    }
+
+    #[test]
+    fn date_and_time_formats() {
+        let days = |param: &str| match SqlParam::parse(param).unwrap().value {
+            ColumnData::Date(Some(date)) => date.days(),
+            ColumnData::DateTime2(Some(dt)) => dt.date().days(),
+            value => panic!("esperada uma data: {:?}", value),
+        };
+
+        let date = days("date:2024-01-31");
+        assert_eq!(days("datetime2:2024-01-31"), date);
+        assert_eq!(days("datetime2:2024-01-31 10:30"), date);
+        assert_eq!(days("datetime2:2024-01-31T10:30:15.1234567"), date);
+        assert!(SqlParam::parse("date:31/01/2024").is_err());
+        assert!(SqlParam::parse("date:2024-02-30").is_err());
+
+        let ColumnData::DateTime2(Some(dt)) = SqlParam::parse("datetime2:2024-01-31 10:30:15.25")
+            .unwrap()
+            .value
+        else {
+            panic!("esperado um datetime2");
+        };
+        assert_eq!(dt.time().increments(), (37_815 * 10_000_000) + 2_500_000);
+
+        assert!(SqlParam::parse("time:25:00").is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use crate::{ObjectName, declared_type, normalize_name};
+use anyhow::{Context, Ok, bail};
+use tiberius::Client;
+use tokio::net::TcpStream;
+use tokio_util::compat::Compat;
+
+/// Tabela temporária da sessão que recebe o valor de retorno
+/// e os parâmetros OUTPUT ao final da execução da procedure.
+const OUTPUT_TABLE: &str = "#rustmssql_output";
+
+/// Nome usado para o valor de retorno na tabela de saída.
+pub const RETURN_VALUE: &str = "RETURN_VALUE";
+
+/// Parâmetro OUTPUT da procedure, informado como `nome=tipo`.
+#[derive(Debug, Clone)]
+pub struct OutputParam {
+    pub name: String,
+    pub sql_type: String,
+}
+
+impl OutputParam {
+    pub fn parse(param: &str) -> anyhow::Result<Self> {
+        //! Interpreta `nome=tipo` de `--output`, como `total=int`
+        //! ou `saldo=decimal(18,2)`.
+
+        let (name, sql_type) = param.split_once('=').with_context(|| {
+            format!(
+                "Parâmetro de saída {} deve estar no formato nome=tipo",
+                param
+            )
+        })?;
+
+        Ok(Self {
+            name: normalize_name(name)?,
+            sql_type: declared_type(sql_type)?,
+        })
+    }
+}
+
+pub fn output_table_sql() -> String {
+    //! Cria a tabela temporária de saída no escopo da sessão,
+    //! visível dentro do `sp_executesql` que executa a procedure.
+
+    format!(
+        "IF OBJECT_ID('tempdb..{table}') IS NOT NULL DROP TABLE {table};
+        CREATE TABLE {table} (position int, name nvarchar(128), value nvarchar(4000))",
+        table = OUTPUT_TABLE
+    )
+}
+
+pub fn procedure_sql(
+    procedure: &ObjectName,
+    positional: usize,
+    named: &[String],
+    outputs: &[OutputParam],
+) -> anyhow::Result<String> {
+    //! Monta o lote que executa a procedure.
+    //! Os argumentos posicionais usam `@P1..@Pn` e os nomeados
+    //! continuam a numeração como `@nome = @Pn`, sem nomes repetidos.
+    //! O valor de retorno e os parâmetros OUTPUT são gravados na tabela de saída.
+
+    for (p, output) in outputs.iter().enumerate() {
+        let name = output.name.to_lowercase();
+        if named.iter().any(|n| n.to_lowercase() == name)
+            || outputs[..p].iter().any(|o| o.name.to_lowercase() == name)
+        {
+            bail!("Parâmetro @{} informado mais de uma vez", output.name);
+        }
+    }
+
+    let mut arguments: Vec<String> = (1..=positional).map(|p| format!("@P{}", p)).collect();
+
+    arguments.extend(
+        named
+            .iter()
+            .enumerate()
+            .map(|(p, name)| format!("@{} = @P{}", name, positional + p + 1)),
+    );
+
+    arguments.extend(
+        outputs
+            .iter()
+            .enumerate()
+            .map(|(p, output)| format!("@{} = @rustmssql_output_{} OUTPUT", output.name, p + 1)),
+    );
+
+    let declarations = std::iter::once("@rustmssql_return int".to_string())
+        .chain(
+            outputs
+                .iter()
+                .enumerate()
+                .map(|(p, output)| format!("@rustmssql_output_{} {}", p + 1, output.sql_type)),
+        )
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let values = std::iter::once(format!(
+        "(0, N'{}', CONVERT(nvarchar(4000), @rustmssql_return))",
+        RETURN_VALUE
+    ))
+    .chain(outputs.iter().enumerate().map(|(p, output)| {
+        // datas no formato ISO 8601
+        let style = if output.sql_type.contains("date") || output.sql_type.contains("time") {
+            ", 126"
+        } else {
+            ""
+        };
+        format!(
+            "({}, N'{}', CONVERT(nvarchar(4000), @rustmssql_output_{}{}))",
+            p + 1,
+            output.name,
+            p + 1,
+            style
+        )
+    }))
+    .collect::<Vec<_>>()
+    .join(", ");
+
+    Ok(format!(
+        "DECLARE {};
+        EXEC @rustmssql_return = {} {};
+        INSERT INTO {} (position, name, value) VALUES {};",
+        declarations,
+        procedure.quoted(),
+        arguments.join(", "),
+        OUTPUT_TABLE,
+        values
+    ))
+}
+
+pub async fn procedure_outputs(
+    client: &mut Client<Compat<TcpStream>>,
+) -> anyhow::Result<Vec<(String, Option<String>)>> {
+    //! Lê o valor de retorno e os parâmetros OUTPUT gravados pela procedure,
+    //! na ordem em que foram declarados.
+
+    let sql = format!(
+        "SELECT name, value FROM {table} ORDER BY position; DROP TABLE {table}",
+        table = OUTPUT_TABLE
+    );
+
+    let rows = client.simple_query(sql).await?.into_first_result().await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| {
+            (
+                row.get::<&str, _>(0).unwrap_or_default().to_string(),
+                row.get::<&str, _>(1).map(|v| v.to_string()),
+            )
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn procedure() -> ObjectName {
+        ObjectName::parse("dbo.relatorio").unwrap()
+    }
+
+    #[test]
+    fn output_param_keeps_the_name_spelling() {
+        let output = OutputParam::parse("@SaldoFinal=decimal(18,2)").unwrap();
+
+        assert_eq!(output.name, "SaldoFinal");
+        assert_eq!(output.sql_type, "decimal(18,2)");
+    }
+
+    #[test]
+    fn output_param_requires_name_and_known_type() {
+        assert!(OutputParam::parse("total").is_err());
+        assert!(OutputParam::parse("=int").is_err());
+        assert!(OutputParam::parse("total=xml").is_err());
+    }
+
+    #[test]
+    fn arguments_follow_the_positional_numbering() {
+        let outputs = vec![OutputParam::parse("Total=int").unwrap()];
+        let names = vec!["DataInicio".to_string()];
+        let sql = procedure_sql(&procedure(), 2, &names, &outputs).unwrap();
+
+        assert!(sql.contains("DECLARE @rustmssql_return int, @rustmssql_output_1 int;"));
+        assert!(sql.contains(
+            "EXEC @rustmssql_return = [dbo].[relatorio] @P1, @P2, @DataInicio = @P3, \
+             @Total = @rustmssql_output_1 OUTPUT;"
+        ));
+        assert!(sql.contains("(1, N'Total', CONVERT(nvarchar(4000), @rustmssql_output_1))"));
+    }
+
+    #[test]
+    fn output_colliding_with_an_input_is_an_error() {
+        let names = vec!["total".to_string()];
+        let outputs = vec![OutputParam::parse("Total=int").unwrap()];
+        assert!(procedure_sql(&procedure(), 0, &names, &outputs).is_err());
+
+        let outputs = vec![
+            OutputParam::parse("total=int").unwrap(),
+            OutputParam::parse("TOTAL=bigint").unwrap(),
+        ];
+        assert!(procedure_sql(&procedure(), 0, &[], &outputs).is_err());
+    }
+}
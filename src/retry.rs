@@ -0,0 +1,150 @@
+use std::io::ErrorKind;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Erros do servidor considerados transitórios,
+/// como banco indisponível ou limite de recursos atingido.
+const TRANSIENT_SERVER_ERRORS: &[u32] = &[
+    20, 64, 233, 4221, 10053, 10054, 10060, 10928, 10929, 40143, 40197, 40501, 40613, 49918, 49919,
+    49920,
+];
+
+/// Política de novas tentativas com backoff exponencial.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn delay(&self, attempt: u32) -> Duration {
+        //! Intervalo antes da próxima tentativa, `initial_delay * 2^attempt`
+        //! limitado a `max_delay`, com até 50% de variação aleatória
+        //! para evitar reconexões simultâneas.
+
+        let base = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos() as u64;
+        let jitter_range = base.as_millis() as u64 / 2;
+        let jitter = nanos.checked_rem(jitter_range).unwrap_or(0);
+
+        base + Duration::from_millis(jitter)
+    }
+}
+
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    //! Verifica se o erro da conexão pode ser resolvido com uma nova tentativa.
+
+    if error
+        .downcast_ref::<tokio::time::error::Elapsed>()
+        .is_some()
+    {
+        return true;
+    }
+
+    if let Some(error) = error.downcast_ref::<std::io::Error>() {
+        return is_transient_io(error.kind());
+    }
+
+    match error.downcast_ref::<tiberius::error::Error>() {
+        Some(tiberius::error::Error::Io { kind, .. }) => is_transient_io(*kind),
+        Some(tiberius::error::Error::Server(token)) => is_transient_code(token.code()),
+        _ => false,
+    }
+}
+
+fn is_transient_code(code: u32) -> bool {
+    //! Falhas de login, como a 18456, não são transitórias.
+
+    TRANSIENT_SERVER_ERRORS.contains(&code)
+}
+
+fn is_transient_io(kind: ErrorKind) -> bool {
+    matches!(
+        kind,
+        ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::ConnectionRefused
+            | ErrorKind::BrokenPipe
+            | ErrorKind::TimedOut
+            | ErrorKind::UnexpectedEof
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(5),
+        };
+
+        // cada intervalo fica entre a base e a base com 50% de variação
+        for (attempt, base) in [(0, 1), (1, 2), (2, 4), (3, 5), (10, 5), (40, 5)] {
+            let base = Duration::from_secs(base);
+            let delay = policy.delay(attempt);
+            assert!(delay >= base && delay <= base * 3 / 2, "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn transient_server_codes() {
+        assert!(is_transient_code(40613));
+        assert!(is_transient_code(10928));
+        assert!(!is_transient_code(18456));
+        assert!(!is_transient_code(208));
+    }
+
+    #[test]
+    fn transient_io_errors() {
+        let io = |kind| anyhow::Error::from(std::io::Error::from(kind));
+        assert!(is_transient(&io(ErrorKind::ConnectionReset)));
+        assert!(is_transient(&io(ErrorKind::TimedOut)));
+        assert!(!is_transient(&io(ErrorKind::PermissionDenied)));
+
+        let tiberius = |kind| {
+            anyhow::Error::from(tiberius::error::Error::Io {
+                kind,
+                message: String::new(),
+            })
+        };
+        assert!(is_transient(&tiberius(ErrorKind::ConnectionRefused)));
+        assert!(!is_transient(&tiberius(ErrorKind::InvalidData)));
+
+        assert!(!is_transient(&anyhow::anyhow!(
+            "Login failed for user 'sa'"
+        )));
+        assert!(!is_transient(&anyhow::Error::from(
+            tiberius::error::Error::Tls("certificado inválido".to_string())
+        )));
+    }
+
+    #[tokio::test]
+    async fn elapsed_timeout_is_transient() {
+        let elapsed = tokio::time::timeout(Duration::ZERO, std::future::pending::<()>())
+            .await
+            .unwrap_err();
+
+        assert!(is_transient(&anyhow::Error::from(elapsed)));
+    }
+}
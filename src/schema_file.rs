@@ -1,5 +1,6 @@
-use crate::MSchema;
 use crate::converter::{Converter, parse_rows};
+use crate::{ColumnCast, GeoColumn, MSchema, apply_casts, geo_metadata, schema_from_metadata};
+use anyhow::Context;
 use indicatif::ProgressBar;
 use parquet::basic::{Compression, ZstdLevel};
 use parquet::file::{properties::WriterProperties, writer::SerializedFileWriter};
@@ -10,7 +11,7 @@ use parquet::{
     format::{MicroSeconds, MilliSeconds},
     schema::types::Type,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
 use std::sync::Arc;
 use std::{fs, path::Path};
@@ -22,29 +23,18 @@ const MAX_GROUP_SIZE: i32 = 100_000;
 fn get_type(col: &str, types: PhysicalType, logical: Option<LogicalType>) -> Type {
     //! Retorna um tipo de dado para o parquet.
 
-    Type::primitive_type_builder(&col, types)
+    Type::primitive_type_builder(col, types)
         .with_logical_type(logical)
         .with_repetition(Repetition::OPTIONAL)
         .build()
         .unwrap()
 }
 
-fn to_type_column(schema: &MSchema) -> Type {
+fn to_type_column(schema: &MSchema, col: &str, options: &ExportOptions) -> Type {
     //! Converte um MSchema para um Type.
     //! Verifica o tipo de dado e retorna um Type.
     //! Se o tipo não for reconhecido, retorna um BYTE_ARRAY.
 
-    let col = schema
-        .column_name
-        .as_ref()
-        .unwrap()
-        .trim()
-        .to_lowercase()
-        .split_whitespace()
-        .map(|f| f.trim())
-        .collect::<Vec<_>>()
-        .join("_");
-
     // converter para o tipo Option<&str> e depos para &str
     let mut opt = schema.data_type.as_deref().unwrap();
 
@@ -52,8 +42,8 @@ fn to_type_column(schema: &MSchema) -> Type {
         opt = &opt[..indice];
     }
 
-    let scale = schema.numeric_scale.unwrap_or(0) as i32;
-    let precision = schema.numeric_precision.unwrap_or(0) as i32;
+    let (precision, scale) = schema.decimal_precision();
+    let (precision, scale) = (precision as i32, scale as i32);
 
     // definir a precisao do tempo
     let datetime_precision = match schema.datetime_precision.unwrap_or(0) {
@@ -69,7 +59,7 @@ fn to_type_column(schema: &MSchema) -> Type {
 
     match opt {
         "tinyint" => get_type(
-            &col,
+            col,
             PhysicalType::INT32,
             Some(LogicalType::Integer {
                 bit_width: 8,
@@ -77,19 +67,19 @@ fn to_type_column(schema: &MSchema) -> Type {
             }),
         ),
         "smallint" => get_type(
-            &col,
+            col,
             PhysicalType::INT32,
             Some(LogicalType::Integer {
                 bit_width: 16,
                 is_signed: true,
             }),
         ),
-        "int" => get_type(&col, PhysicalType::INT32, None),
-        "bigint" => get_type(&col, PhysicalType::INT64, None),
-        "float" => get_type(&col, PhysicalType::DOUBLE, None),
-        "real" => get_type(&col, PhysicalType::FLOAT, None),
-        "decimal" | "numeric" => {
-            Type::primitive_type_builder(&col, PhysicalType::FIXED_LEN_BYTE_ARRAY)
+        "int" => get_type(col, PhysicalType::INT32, None),
+        "bigint" => get_type(col, PhysicalType::INT64, None),
+        "float" => get_type(col, PhysicalType::DOUBLE, None),
+        "real" => get_type(col, PhysicalType::FLOAT, None),
+        "decimal" | "numeric" | "money" | "smallmoney" => {
+            Type::primitive_type_builder(col, PhysicalType::FIXED_LEN_BYTE_ARRAY)
                 .with_length(length_in_bytes.try_into().unwrap())
                 .with_logical_type(Some(LogicalType::Decimal { scale, precision }))
                 .with_precision(precision)
@@ -98,41 +88,213 @@ fn to_type_column(schema: &MSchema) -> Type {
                 .build()
                 .unwrap()
         }
-        "bit" => get_type(&col, PhysicalType::BOOLEAN, None),
+        "bit" => get_type(col, PhysicalType::BOOLEAN, None),
         "char" | "varchar" | "text" | "nchar" | "nvarchar" | "ntext" | "xml" => {
-            get_type(&col, PhysicalType::BYTE_ARRAY, Some(LogicalType::String))
+            get_type(col, PhysicalType::BYTE_ARRAY, Some(LogicalType::String))
         }
         "datetime" | "datetime2" | "smalldatetime" => get_type(
-            &col,
+            col,
             PhysicalType::INT64,
             Some(LogicalType::Timestamp {
                 is_adjusted_to_u_t_c: false,
                 unit: datetime_precision,
             }),
         ),
-        "date" => get_type(&col, PhysicalType::INT32, Some(LogicalType::Date)),
+        "datetimeoffset" => get_type(
+            col,
+            PhysicalType::INT64,
+            Some(LogicalType::Timestamp {
+                is_adjusted_to_u_t_c: true,
+                unit: datetime_precision,
+            }),
+        ),
+        // coluna auxiliar com o fuso do datetimeoffset
+        "offset_minutes" => get_type(
+            col,
+            PhysicalType::INT32,
+            Some(LogicalType::Integer {
+                bit_width: 16,
+                is_signed: true,
+            }),
+        ),
+        "date" => get_type(col, PhysicalType::INT32, Some(LogicalType::Date)),
+        // TIME em milissegundos exige INT32, até a precisão 6 usa microssegundos
         "time" => get_type(
-            &col,
+            col,
             PhysicalType::INT64,
             Some(LogicalType::Time {
                 is_adjusted_to_u_t_c: false,
-                unit: datetime_precision,
+                unit: match datetime_precision {
+                    TimeUnit::MILLIS(_) => TimeUnit::MICROS(MicroSeconds {}),
+                    unit => unit,
+                },
             }),
         ),
-        "binary" | "varbinary" | "image" => get_type(&col, PhysicalType::BYTE_ARRAY, None),
-        _ => get_type(&col, PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+        "uniqueidentifier" if options.guid_format == GuidFormat::Uuid => {
+            Type::primitive_type_builder(col, PhysicalType::FIXED_LEN_BYTE_ARRAY)
+                .with_length(16)
+                .with_logical_type(Some(LogicalType::Uuid))
+                .with_repetition(Repetition::OPTIONAL)
+                .build()
+                .unwrap()
+        }
+        "binary" | "varbinary" | "image" if options.binary_format != BinaryFormat::Raw => {
+            get_type(col, PhysicalType::BYTE_ARRAY, Some(LogicalType::String))
+        }
+        "binary" if schema.binary_length().is_some() => {
+            Type::primitive_type_builder(col, PhysicalType::FIXED_LEN_BYTE_ARRAY)
+                .with_length(schema.binary_length().unwrap() as i32)
+                .with_repetition(Repetition::OPTIONAL)
+                .build()
+                .unwrap()
+        }
+        "binary" | "varbinary" | "image" => get_type(col, PhysicalType::BYTE_ARRAY, None),
+        // WKB, descrito nos metadados `geo` do GeoParquet
+        "geography" | "geometry" => get_type(col, PhysicalType::BYTE_ARRAY, None),
+        _ => get_type(col, PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+    }
+}
+
+/// Caracteres rejeitados em nomes de coluna por engines como Spark e Hive.
+const INVALID_NAME_CHARS: &[char] = &[',', ';', '{', '}', '(', ')', '=', '\n', '\t'];
+
+/// Como os nomes das colunas são gravados no parquet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum ColumnNaming {
+    /// Mantém o nome original
+    Preserve,
+    /// Letras minúsculas
+    #[default]
+    Lower,
+    /// Minúsculas separadas por `_`, `ValorTotal` vira `valor_total`
+    SnakeCase,
+}
+
+/// Como as colunas `uniqueidentifier` são gravadas no parquet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum GuidFormat {
+    /// 16 bytes com o tipo lógico UUID
+    #[default]
+    Uuid,
+    /// Texto no formato canônico `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`
+    String,
+}
+
+/// Como as colunas `binary`, `varbinary` e `image` são gravadas no parquet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum BinaryFormat {
+    /// Bytes sem conversão, `binary(n)` com tamanho fixo
+    #[default]
+    Raw,
+    /// Texto hexadecimal em minúsculas
+    Hex,
+    /// Texto em base64
+    Base64,
+}
+
+/// Opções de gravação dos arquivos parquet.
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    pub column_naming: ColumnNaming,
+    pub casts: Vec<ColumnCast>,
+    pub guid_format: GuidFormat,
+    pub offset_column: bool,
+    pub binary_format: BinaryFormat,
+}
+
+fn to_snake_case(name: &str) -> String {
+    //! Separa as palavras por `_`, nas trocas de minúscula para maiúscula
+    //! e nos caracteres que não são letras ou números.
+
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::with_capacity(name.len());
+
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            snake.push('_');
+            continue;
+        }
+
+        if c.is_uppercase() && i > 0 {
+            let previous = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            // `ValorTotal` e `HTTPStatus` viram `valor_total` e `http_status`
+            if previous.is_lowercase()
+                || previous.is_numeric()
+                || (previous.is_uppercase() && next_lower)
+            {
+                snake.push('_');
+            }
+        }
+        snake.extend(c.to_lowercase());
+    }
+
+    snake
+        .split('_')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+fn apply_naming(name: &str, naming: ColumnNaming) -> String {
+    //! Aplica o padrão de nomes e remove os caracteres rejeitados,
+    //! espaços viram `_`.
+
+    let name = match naming {
+        ColumnNaming::Preserve => name.to_string(),
+        ColumnNaming::Lower => name.to_lowercase(),
+        ColumnNaming::SnakeCase => to_snake_case(name),
+    };
+
+    name.chars()
+        .filter(|c| !INVALID_NAME_CHARS.contains(c) && !c.is_control())
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+pub fn column_names(schema_sql: &[MSchema], naming: ColumnNaming) -> Vec<String> {
+    //! Nomes das colunas no parquet.
+    //! Colunas sem nome, como `SELECT count(*)`, viram `col_N` pela posição
+    //! e nomes repetidos recebem um sufixo, `id`, `id_1`, `id_2`.
+    //! A comparação ignora maiúsculas, como na maioria das engines.
+
+    let mut used: HashSet<String> = HashSet::new();
+    let mut names: Vec<String> = Vec::with_capacity(schema_sql.len());
+
+    for (position, mssql) in schema_sql.iter().enumerate() {
+        let name = mssql
+            .column_name
+            .as_deref()
+            .map(|name| apply_naming(name, naming))
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| format!("col_{}", position + 1));
+
+        let mut unique = name.clone();
+        let mut suffix = 1;
+        while used.contains(&unique.to_lowercase()) {
+            unique = format!("{}_{}", name, suffix);
+            suffix += 1;
+        }
+
+        used.insert(unique.to_lowercase());
+        names.push(unique);
     }
+
+    names
 }
 
-pub fn create_schema_parquet(sql_types: &Vec<MSchema>) -> Type {
+pub fn create_schema_parquet(sql_types: &[MSchema], options: &ExportOptions) -> Type {
     //! Cria um schema parquet a partir de um MSchema.
     //! Recebe um MSchema e retorna um Type.
     //! O Type é um schema parquet.
 
+    let names = column_names(sql_types, options.column_naming);
     let mut fields = vec![];
 
-    for mssql in sql_types {
-        let data = to_type_column(mssql);
+    for (mssql, name) in sql_types.iter().zip(&names) {
+        let data = to_type_column(mssql, name, options);
         let tp = Arc::new(data);
 
         fields.push(tp);
@@ -144,45 +306,49 @@ pub fn create_schema_parquet(sql_types: &Vec<MSchema>) -> Type {
         .unwrap()
 }
 
-async fn process_rows<W: Write>(
-    schema_sql: &Vec<MSchema>,
+async fn process_rows<W>(
+    schema_sql: &[MSchema],
+    sources: &[usize],
     data: &mut HashMap<usize, Vec<ColumnData<'_>>>,
     writer: &mut SerializedFileWriter<W>,
+    binary_format: BinaryFormat,
+    geo_columns: &mut HashMap<usize, GeoColumn>,
 ) -> anyhow::Result<()>
 where
-    W: Send,
+    W: Write + Send,
 {
+    let physical_types: Vec<PhysicalType> = writer
+        .schema_descr()
+        .columns()
+        .iter()
+        .map(|column| column.physical_type())
+        .collect();
+
     let mut col_key: usize = 0;
     let mut row_group_writer = writer.next_row_group()?;
     while let Some(col_write) = row_group_writer.next_column()? {
-        let col_data = data.get(&col_key).unwrap();
+        let col_data = data.get(&sources[col_key]).unwrap();
         let mssql = schema_sql.get(col_key).unwrap();
 
         let conv = Some(Converter {
             col_data,
             col_write: Some(col_write),
             mssql: Some(mssql),
+            binary_format,
+            geo: geo_columns.get_mut(&col_key),
         });
 
-        match &col_data[0] {
-            ColumnData::I32(_) => parse_rows::<i32>(conv)?,
-            ColumnData::String(_) => parse_rows::<ByteArray>(conv)?,
-            ColumnData::U8(_) => parse_rows::<i32>(conv)?,
-            ColumnData::I16(_) => parse_rows::<i32>(conv)?,
-            ColumnData::I64(_) => parse_rows::<i64>(conv)?,
-            ColumnData::F32(_) => parse_rows::<f32>(conv)?,
-            ColumnData::F64(_) => parse_rows::<f64>(conv)?,
-            ColumnData::Numeric(_) => parse_rows::<FixedLenByteArray>(conv)?,
-            ColumnData::Bit(_) => parse_rows::<bool>(conv)?,
-            ColumnData::DateTime(_) => parse_rows::<i64>(conv)?,
-            ColumnData::DateTime2(_) => parse_rows::<i64>(conv)?,
-            ColumnData::Time(_) => parse_rows::<i64>(conv)?,
-            ColumnData::Date(_) => parse_rows::<i32>(conv)?,
-            ColumnData::Xml(_) => parse_rows::<ByteArray>(conv)?,
-            _ => {
-                println!("Tipo de dado não reconhecido, {:?}", col_data[0]);
-                unimplemented!()
-            }
+        // o conversor segue o tipo fisico da coluna no parquet,
+        // que ja considera as conversoes de `--cast`
+        match physical_types[col_key] {
+            PhysicalType::BOOLEAN => parse_rows::<bool>(conv)?,
+            PhysicalType::INT32 => parse_rows::<i32>(conv)?,
+            PhysicalType::INT64 => parse_rows::<i64>(conv)?,
+            PhysicalType::FLOAT => parse_rows::<f32>(conv)?,
+            PhysicalType::DOUBLE => parse_rows::<f64>(conv)?,
+            PhysicalType::BYTE_ARRAY => parse_rows::<ByteArray>(conv)?,
+            PhysicalType::FIXED_LEN_BYTE_ARRAY => parse_rows::<FixedLenByteArray>(conv)?,
+            PhysicalType::INT96 => unreachable!(),
         };
         col_key += 1;
     }
@@ -193,60 +359,406 @@ where
     Ok(())
 }
 
+pub fn result_set_path(path: &str, number: usize) -> String {
+    //! Nome do arquivo de cada conjunto de resultados,
+    //! `saida.parquet` vira `saida_1.parquet`, `saida_2.parquet`, ...
+
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}_{}.{}", stem, number, extension.to_string_lossy()),
+        None => format!("{}_{}", stem, number),
+    };
+
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+fn number_first_file(path: &str, files: &mut [String]) -> anyhow::Result<()> {
+    //! Com o segundo conjunto de resultados o arquivo do primeiro
+    //! passa a ser numerado, `saida.parquet` vira `saida_1.parquet`.
+
+    let first = result_set_path(path, 1);
+    fs::rename(path, &first)
+        .with_context(|| format!("Falha ao renomear {} para {}", path, first))?;
+    files[0] = first;
+
+    Ok(())
+}
+
+fn refine_schema(schema_sql: &mut [MSchema], data: &HashMap<usize, Vec<ColumnData<'_>>>) {
+    //! Ajusta os metadados obtidos do fluxo com o primeiro valor não nulo
+    //! de cada coluna, que informa a escala dos decimais e das datas.
+
+    for (position, mssql) in schema_sql.iter_mut().enumerate() {
+        let Some(values) = data.get(&position) else {
+            continue;
+        };
+
+        for value in values {
+            match value {
+                ColumnData::Numeric(Some(n)) => mssql.numeric_scale = Some(n.scale()),
+                ColumnData::DateTime2(Some(dt)) => {
+                    mssql.datetime_precision = Some(dt.time().scale())
+                }
+                ColumnData::Time(Some(t)) => mssql.datetime_precision = Some(t.scale()),
+                ColumnData::DateTimeOffset(Some(dto)) => {
+                    mssql.datetime_precision = Some(dto.datetime2().time().scale())
+                }
+                ColumnData::SmallDateTime(Some(_)) => {
+                    mssql.data_type = Some("smalldatetime".to_string());
+                    mssql.datetime_precision = Some(0);
+                }
+                _ => continue,
+            }
+            break;
+        }
+    }
+}
+
+fn expand_offset_columns(schema_sql: Vec<MSchema>) -> (Vec<MSchema>, Vec<usize>) {
+    //! Acrescenta após cada coluna `datetimeoffset` a coluna `<nome>_offset`
+    //! com o fuso original em minutos.
+    //! Retorna as colunas do parquet e a coluna de origem dos dados de cada uma.
+
+    let mut columns: Vec<MSchema> = Vec::with_capacity(schema_sql.len());
+    let mut sources: Vec<usize> = Vec::with_capacity(schema_sql.len());
+
+    for (position, mssql) in schema_sql.into_iter().enumerate() {
+        let offset = (mssql.base_type() == "datetimeoffset").then(|| MSchema {
+            column_name: Some(format!(
+                "{}_offset",
+                mssql.column_name.as_deref().unwrap_or_default()
+            )),
+            data_type: Some("offset_minutes".to_string()),
+            is_nullable: mssql.is_nullable.clone(),
+            numeric_precision: None,
+            numeric_scale: None,
+            datetime_precision: None,
+        });
+
+        columns.push(mssql);
+        sources.push(position);
+
+        if let Some(offset) = offset {
+            columns.push(offset);
+            sources.push(position);
+        }
+    }
+
+    (columns, sources)
+}
+
+/// Arquivo parquet de um conjunto de resultados da consulta.
+struct ResultSetFile {
+    path: String,
+    options: ExportOptions,
+    schema_sql: Vec<MSchema>,
+    sources: Vec<usize>,
+    from_metadata: bool,
+    writer: Option<SerializedFileWriter<fs::File>>,
+    data: HashMap<usize, Vec<ColumnData<'static>>>,
+    rows: i32,
+    geo_columns: HashMap<usize, GeoColumn>,
+}
+
+impl ResultSetFile {
+    fn new(
+        path: String,
+        options: &ExportOptions,
+        schema_sql: Vec<MSchema>,
+        from_metadata: bool,
+    ) -> Self {
+        Self {
+            path,
+            options: options.clone(),
+            sources: (0..schema_sql.len()).collect(),
+            schema_sql,
+            from_metadata,
+            writer: None,
+            data: HashMap::new(),
+            rows: 0,
+            geo_columns: HashMap::new(),
+        }
+    }
+
+    async fn flush(&mut self, files: &mut Vec<String>) -> anyhow::Result<()> {
+        //! Grava os registros acumulados em um novo row group.
+        //! O arquivo é criado na primeira gravação, quando os dados
+        //! já permitem ajustar os metadados vindos do fluxo,
+        //! e o caminho é registrado em `files` logo após a criação.
+
+        if self.writer.is_none() {
+            if self.from_metadata {
+                refine_schema(&mut self.schema_sql, &self.data);
+            }
+
+            let names = column_names(&self.schema_sql, self.options.column_naming);
+            for column in apply_casts(&mut self.schema_sql, &names, &self.options.casts) {
+                eprintln!(
+                    "Conversão ignorada em {}: coluna {} não encontrada",
+                    self.path, column
+                );
+            }
+
+            if self.options.offset_column {
+                let schema_sql = std::mem::take(&mut self.schema_sql);
+                (self.schema_sql, self.sources) = expand_offset_columns(schema_sql);
+            }
+
+            let names = column_names(&self.schema_sql, self.options.column_naming);
+            for (position, (mssql, name)) in self.schema_sql.iter().zip(names).enumerate() {
+                let data_type = mssql.base_type();
+                if matches!(data_type.as_str(), "geography" | "geometry") {
+                    let geo_column = GeoColumn {
+                        name,
+                        geography: data_type == "geography",
+                        ..Default::default()
+                    };
+                    self.geo_columns.insert(position, geo_column);
+                }
+            }
+
+            let schema = Arc::new(create_schema_parquet(&self.schema_sql, &self.options));
+            let file = fs::File::create(Path::new(&self.path))
+                .with_context(|| format!("Falha ao criar o arquivo {}", self.path))?;
+            files.push(self.path.clone());
+
+            let props = WriterProperties::builder()
+                .set_compression(Compression::ZSTD(ZstdLevel::try_new(1)?))
+                .build()
+                .into();
+
+            self.writer = Some(SerializedFileWriter::new(file, schema, props)?);
+        }
+
+        if !self.data.is_empty() {
+            let writer = self.writer.as_mut().unwrap();
+            process_rows(
+                &self.schema_sql,
+                &self.sources,
+                &mut self.data,
+                writer,
+                self.options.binary_format,
+                &mut self.geo_columns,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn finish(mut self, files: &mut Vec<String>) -> anyhow::Result<()> {
+        self.flush(files).await?;
+
+        let mut writer = self.writer.take().unwrap();
+        let mut geo_columns: Vec<(usize, GeoColumn)> = self.geo_columns.into_iter().collect();
+        geo_columns.sort_by_key(|(position, _)| *position);
+        let geo_columns: Vec<GeoColumn> = geo_columns.into_iter().map(|(_, c)| c).collect();
+        if let Some(geo) = geo_metadata(&geo_columns) {
+            writer.append_key_value_metadata(geo);
+        }
+        writer.close()?;
+
+        Ok(())
+    }
+}
+
 pub async fn write_parquet_from_stream(
     mut stream: QueryStream<'_>,
-    schema: Arc<Type>,
-    schema_sql: &Vec<MSchema>,
+    schema_sql: &[MSchema],
+    options: &ExportOptions,
     path: &str,
     progress: &ProgressBar,
+    files: &mut Vec<String>,
 ) -> anyhow::Result<()> {
-    //! Escreve um arquivo parquet a partir de um QueryStream.
-    //! Recebe um QueryStream, um Arc<Type> e um &str.
-    //! O Arc<Type> é o schema parquet.
-    //! O &str é o caminho do arquivo parquet.
-    //! Retorna um Result<()>.
-
-    let path_new = Path::new(path);
-    let file = fs::File::create(&path_new).unwrap();
+    //! Escreve os arquivos parquet a partir de um QueryStream.
+    //! O primeiro conjunto de resultados usa o schema de `schema_sql`,
+    //! ou os metadados do fluxo quando `schema_sql` está vazio,
+    //! e é gravado em `path`. Quando a consulta retorna outros conjuntos,
+    //! cada um ganha o seu arquivo (`saida_1.parquet`, `saida_2.parquet`, ...)
+    //! com o schema montado a partir dos metadados do próprio conjunto.
+    //! Os caminhos são registrados em `files` à medida que os arquivos
+    //! são criados, inclusive quando a exportação falha ou é cancelada,
+    //! para que apenas os arquivos desta execução sejam removidos.
 
-    let props = WriterProperties::builder()
-        .set_compression(Compression::ZSTD(ZstdLevel::try_new(1)?))
-        .build()
-        .into();
-
-    let mut writer = SerializedFileWriter::new(file, schema, props)?;
-    let mut data: HashMap<usize, Vec<ColumnData>> = HashMap::new();
+    let mut current: Option<ResultSetFile> = None;
 
-    // armazena os dados
-    let mut rows_batch: i32 = 1;
+    // total de registros de todos os conjuntos
+    let mut rows_total: i32 = 0;
 
     progress.set_message("Inicio da Exportacao ...");
 
-    while let Some(row) = stream.try_next().await? {
-        if let QueryItem::Row(r) = row {
-            for (p, col_data) in r.into_iter().enumerate() {
-                data.entry(p).or_insert_with(Vec::new).push(col_data);
+    while let Some(item) = stream.try_next().await? {
+        match item {
+            QueryItem::Metadata(metadata) => {
+                if let Some(result_set) = current.take() {
+                    result_set.finish(files).await?;
+                }
+
+                let result_set = match metadata.result_index() {
+                    0 if !schema_sql.is_empty() => {
+                        ResultSetFile::new(path.to_string(), options, schema_sql.to_vec(), false)
+                    }
+                    0 => ResultSetFile::new(
+                        path.to_string(),
+                        options,
+                        schema_from_metadata(metadata.columns()),
+                        true,
+                    ),
+                    index => {
+                        if index == 1 {
+                            number_first_file(path, files)?;
+                        }
+
+                        ResultSetFile::new(
+                            result_set_path(path, index + 1),
+                            options,
+                            schema_from_metadata(metadata.columns()),
+                            true,
+                        )
+                    }
+                };
+
+                if metadata.result_index() > 0 {
+                    progress.println(format!(
+                        "Conjunto de resultados {} em {}",
+                        metadata.result_index() + 1,
+                        result_set.path
+                    ));
+                }
+
+                current = Some(result_set);
             }
-
-            if rows_batch % MAX_GROUP_SIZE == 0 {
-                progress.set_message(format!("Gravando {} regitros ...", rows_batch));
-                process_rows(&schema_sql, &mut data, &mut writer).await?;
+            QueryItem::Row(r) => {
+                let result_set = current
+                    .as_mut()
+                    .context("Registro recebido sem os metadados do conjunto")?;
+
+                for (p, col_data) in r.into_iter().enumerate() {
+                    result_set.data.entry(p).or_default().push(col_data);
+                }
+
+                result_set.rows += 1;
+                rows_total += 1;
+
+                if result_set.rows % MAX_GROUP_SIZE == 0 {
+                    progress.set_message(format!("Gravando {} regitros ...", rows_total));
+                    result_set.flush(files).await?;
+                }
             }
-            rows_batch += 1;
         }
     }
 
-    if !data.is_empty() {
-        progress.set_message(format!("Gravando {} regitros ...", rows_batch));
-        process_rows(&schema_sql, &mut data, &mut writer).await?;
+    if let Some(result_set) = current.take() {
+        progress.set_message(format!("Gravando {} regitros ...", rows_total));
+        result_set.finish(files).await?;
     }
 
-    writer.close()?;
-
     progress.finish_with_message(format!(
         "Finalizados {} registros exportados ... ✅",
-        rows_batch
+        rows_total
     ));
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(names: &[Option<&str>]) -> Vec<MSchema> {
+        names
+            .iter()
+            .map(|name| MSchema {
+                column_name: name.map(|n| n.to_string()),
+                data_type: Some("int".to_string()),
+                is_nullable: Some("YES".to_string()),
+                numeric_precision: None,
+                numeric_scale: None,
+                datetime_precision: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unnamed_columns_use_the_position() {
+        let names = column_names(
+            &schema(&[Some("id"), Some(""), None, Some("  ")]),
+            ColumnNaming::Lower,
+        );
+
+        assert_eq!(names, vec!["id", "col_2", "col_3", "col_4"]);
+    }
+
+    #[test]
+    fn repeated_names_get_a_suffix() {
+        let names = column_names(
+            &schema(&[Some("id"), Some("ID"), Some("id_1"), Some("id")]),
+            ColumnNaming::Preserve,
+        );
+
+        assert_eq!(names, vec!["id", "ID_1", "id_1_1", "id_2"]);
+    }
+
+    #[test]
+    fn snake_case_names() {
+        assert_eq!(to_snake_case("ValorTotal"), "valor_total");
+        assert_eq!(to_snake_case("ABCValue"), "abc_value");
+        assert_eq!(to_snake_case("HTTPStatus2xx"), "http_status2xx");
+        assert_eq!(to_snake_case("Data de Emissão"), "data_de_emissão");
+        assert_eq!(to_snake_case("  valor -- total "), "valor_total");
+        assert_eq!(
+            apply_naming("Nome Cliente", ColumnNaming::Preserve),
+            "Nome_Cliente"
+        );
+    }
+
+    #[test]
+    fn result_set_paths() {
+        assert_eq!(result_set_path("saida.parquet", 2), "saida_2.parquet");
+        assert_eq!(
+            result_set_path("dados/saida.v1.parquet", 1),
+            "dados/saida.v1_1.parquet"
+        );
+        assert_eq!(result_set_path("saida", 3), "saida_3");
+    }
+
+    async fn write_result_set(path: &str, files: &mut Vec<String>) {
+        let mut result_set = ResultSetFile::new(
+            path.to_string(),
+            &ExportOptions::default(),
+            schema(&[Some("id")]),
+            false,
+        );
+        result_set.data.insert(0, vec![ColumnData::I32(Some(1))]);
+        result_set.finish(files).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn result_sets_are_numbered_from_the_second() {
+        let dir =
+            std::env::temp_dir().join(format!("rustmssql_result_sets_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("saida.parquet").display().to_string();
+        let mut files = Vec::new();
+
+        // um único conjunto mantém o nome informado
+        write_result_set(&path, &mut files).await;
+        assert_eq!(files, vec![path.clone()]);
+        assert!(Path::new(&path).exists());
+
+        // o segundo conjunto renomeia o arquivo do primeiro
+        number_first_file(&path, &mut files).unwrap();
+        write_result_set(&result_set_path(&path, 2), &mut files).await;
+
+        let numbered = vec![
+            dir.join("saida_1.parquet").display().to_string(),
+            dir.join("saida_2.parquet").display().to_string(),
+        ];
+        assert_eq!(files, numbered);
+        assert!(!Path::new(&path).exists());
+        assert!(numbered.iter().all(|file| Path::new(file).exists()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
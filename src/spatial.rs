@@ -0,0 +1,411 @@
+use anyhow::{Context, Ok, bail};
+use parquet::format::KeyValue;
+use std::collections::BTreeSet;
+
+/// SRID padrão do `geography`, coordenadas em longitude e latitude.
+const WGS84: i32 = 4326;
+
+// propriedades da serialização (MS-SSCLRT)
+const HAS_Z: u8 = 0x01;
+const HAS_M: u8 = 0x02;
+const SINGLE_POINT: u8 = 0x08;
+const SINGLE_LINE: u8 = 0x10;
+
+/// Geometria convertida para WKB, com o SRID e o tipo no formato do GeoParquet.
+pub struct Geometry {
+    pub srid: i32,
+    pub geometry_type: String,
+    pub wkb: Vec<u8>,
+}
+
+/// SRIDs e tipos encontrados em uma coluna espacial,
+/// gravados nos metadados GeoParquet ao final do arquivo.
+#[derive(Debug, Clone, Default)]
+pub struct GeoColumn {
+    pub name: String,
+    pub geography: bool,
+    pub srids: BTreeSet<i32>,
+    pub geometry_types: BTreeSet<String>,
+}
+
+impl GeoColumn {
+    pub fn add(&mut self, geometry: &Geometry) {
+        self.srids.insert(geometry.srid);
+        self.geometry_types.insert(geometry.geometry_type.clone());
+    }
+
+    fn crs(&self) -> Option<serde_json::Value> {
+        //! Sem o `crs` o GeoParquet assume OGC:CRS84, equivalente ao 4326
+        //! em longitude e latitude. SRID 0 ou vários SRIDs na mesma coluna
+        //! ficam com `crs` nulo, sistema de referência desconhecido.
+
+        let srids: Vec<i32> = self.srids.iter().copied().collect();
+        match srids.as_slice() {
+            [] | [WGS84] => None,
+            [srid] if *srid > 0 => Some(serde_json::json!({
+                "id": { "authority": "EPSG", "code": srid }
+            })),
+            _ => Some(serde_json::Value::Null),
+        }
+    }
+}
+
+pub fn geo_metadata(columns: &[GeoColumn]) -> Option<KeyValue> {
+    //! Metadados `geo` do GeoParquet 1.1, a primeira coluna espacial
+    //! é a coluna principal.
+
+    let primary = columns.first()?;
+
+    let mut metadata = serde_json::Map::new();
+    for column in columns {
+        let mut value = serde_json::json!({
+            "encoding": "WKB",
+            "geometry_types": column.geometry_types,
+        });
+
+        if let Some(crs) = column.crs() {
+            value["crs"] = crs;
+        }
+        if column.geography {
+            value["edges"] = "spherical".into();
+        }
+        metadata.insert(column.name.clone(), value);
+    }
+
+    let geo = serde_json::json!({
+        "version": "1.1.0",
+        "primary_column": primary.name,
+        "columns": metadata,
+    });
+
+    Some(KeyValue::new("geo".to_string(), geo.to_string()))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, size: usize) -> anyhow::Result<&[u8]> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + size)
+            .context("Valor espacial incompleto")?;
+        self.position += size;
+
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn i32(&mut self) -> anyhow::Result<i32> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn count(&mut self, item_size: usize) -> anyhow::Result<usize> {
+        //! Quantidade de itens de `item_size` bytes, validada contra os bytes
+        //! restantes antes de qualquer alocação.
+
+        let count = u32::from_le_bytes(self.take(4)?.try_into()?) as usize;
+        if count * item_size > self.bytes.len() - self.position {
+            bail!("Valor espacial incompleto");
+        }
+
+        Ok(count)
+    }
+
+    fn f64(&mut self) -> anyhow::Result<f64> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+}
+
+struct Shape {
+    parent: i32,
+    figure: i32,
+    kind: u8,
+}
+
+/// Geometria desserializada, com os pontos, figuras e formas do SQL Server.
+struct ClrGeometry {
+    points: Vec<[f64; 2]>,
+    z: Option<Vec<f64>>,
+    figures: Vec<usize>,
+    shapes: Vec<Shape>,
+}
+
+pub fn clr_to_wkb(bytes: &[u8], geography: bool) -> anyhow::Result<Geometry> {
+    //! Converte a serialização CLR de `geography` e `geometry` para WKB ISO
+    //! em little-endian. No `geography` a latitude vem antes da longitude
+    //! e o WKB segue a ordem longitude, latitude do GeoParquet.
+    //! Os valores M são descartados; arcos e `FULLGLOBE` não têm WKB.
+
+    let mut reader = Reader { bytes, position: 0 };
+
+    let srid = reader.i32()?;
+    let version = reader.u8()?;
+    if !matches!(version, 1 | 2) {
+        bail!("Versão {} da serialização espacial não suportada", version);
+    }
+    let properties = reader.u8()?;
+
+    let single = properties & (SINGLE_POINT | SINGLE_LINE) != 0;
+    let num_points = match properties {
+        p if p & SINGLE_POINT != 0 => 1,
+        p if p & SINGLE_LINE != 0 => 2,
+        _ => reader.count(16)?,
+    };
+    if num_points * 16 > bytes.len() - reader.position {
+        bail!("Valor espacial incompleto");
+    }
+
+    let mut points = Vec::with_capacity(num_points);
+    for _ in 0..num_points {
+        let (first, second) = (reader.f64()?, reader.f64()?);
+        points.push(if geography {
+            [second, first]
+        } else {
+            [first, second]
+        });
+    }
+
+    let z = match properties & HAS_Z {
+        0 => None,
+        _ => Some(
+            (0..num_points)
+                .map(|_| reader.f64())
+                .collect::<Result<_, _>>()?,
+        ),
+    };
+    if properties & HAS_M != 0 {
+        reader.take(num_points * 8)?;
+    }
+
+    let (figures, shapes) = if single {
+        let kind = if num_points == 1 { 1 } else { 2 };
+        (
+            vec![0],
+            vec![Shape {
+                parent: -1,
+                figure: 0,
+                kind,
+            }],
+        )
+    } else {
+        let num_figures = reader.count(5)?;
+        let mut figures = Vec::with_capacity(num_figures);
+        for _ in 0..num_figures {
+            let _attribute = reader.u8()?;
+            figures.push(reader.i32()?.max(0) as usize);
+        }
+
+        let num_shapes = reader.count(9)?;
+        let mut shapes = Vec::with_capacity(num_shapes);
+        for _ in 0..num_shapes {
+            shapes.push(Shape {
+                parent: reader.i32()?,
+                figure: reader.i32()?,
+                kind: reader.u8()?,
+            });
+        }
+
+        (figures, shapes)
+    };
+
+    if shapes.is_empty() {
+        bail!("Valor espacial sem formas");
+    }
+
+    let geometry = ClrGeometry {
+        points,
+        z,
+        figures,
+        shapes,
+    };
+
+    let mut wkb = Vec::new();
+    geometry.write_shape(0, &mut wkb)?;
+
+    let mut geometry_type = shape_name(geometry.shapes[0].kind)?.to_string();
+    if geometry.z.is_some() {
+        geometry_type.push_str(" Z");
+    }
+
+    Ok(Geometry {
+        srid,
+        geometry_type,
+        wkb,
+    })
+}
+
+fn shape_name(kind: u8) -> anyhow::Result<&'static str> {
+    Ok(match kind {
+        1 => "Point",
+        2 => "LineString",
+        3 => "Polygon",
+        4 => "MultiPoint",
+        5 => "MultiLineString",
+        6 => "MultiPolygon",
+        7 => "GeometryCollection",
+        8 => bail!("CIRCULARSTRING não é suportado no WKB"),
+        9 => bail!("COMPOUNDCURVE não é suportado no WKB"),
+        10 => bail!("CURVEPOLYGON não é suportado no WKB"),
+        11 => bail!("FULLGLOBE não é suportado no WKB"),
+        _ => bail!("Tipo espacial {} inválido", kind),
+    })
+}
+
+impl ClrGeometry {
+    fn figure_points(&self, figure: usize) -> anyhow::Result<std::ops::Range<usize>> {
+        let start = self.figures[figure];
+        let end = self
+            .figures
+            .get(figure + 1)
+            .copied()
+            .unwrap_or(self.points.len());
+
+        if start > end || end > self.points.len() {
+            bail!("Figura espacial inválida");
+        }
+
+        Ok(start..end)
+    }
+
+    fn shape_figures(&self, shape: usize) -> anyhow::Result<std::ops::Range<usize>> {
+        //! Figuras de uma forma simples, até a figura da próxima forma.
+        //! Formas vazias têm a figura -1.
+
+        let Some(start) = usize::try_from(self.shapes[shape].figure).ok() else {
+            return Ok(0..0);
+        };
+
+        let end = self.shapes[shape + 1..]
+            .iter()
+            .find_map(|next| usize::try_from(next.figure).ok())
+            .unwrap_or(self.figures.len());
+
+        if start > end || end > self.figures.len() {
+            bail!("Forma espacial inválida");
+        }
+
+        Ok(start..end)
+    }
+
+    fn write_points(&self, points: std::ops::Range<usize>, wkb: &mut Vec<u8>) {
+        for point in points {
+            for coordinate in self.points[point] {
+                wkb.extend(coordinate.to_le_bytes());
+            }
+            if let Some(z) = &self.z {
+                wkb.extend(z[point].to_le_bytes());
+            }
+        }
+    }
+
+    fn write_shape(&self, shape: usize, wkb: &mut Vec<u8>) -> anyhow::Result<()> {
+        let kind = self.shapes[shape].kind;
+        shape_name(kind)?;
+
+        // byte order little-endian e tipo ISO, 1000 a mais com Z
+        wkb.push(1);
+        let dimension = if self.z.is_some() { 1000 } else { 0 };
+        wkb.extend((kind as u32 + dimension).to_le_bytes());
+
+        match kind {
+            1 => {
+                let points = match self.shape_figures(shape)?.next() {
+                    Some(figure) => self.figure_points(figure)?,
+                    None => 0..0,
+                };
+                if points.is_empty() {
+                    // POINT EMPTY é gravado com coordenadas NaN
+                    let dimensions = if self.z.is_some() { 3 } else { 2 };
+                    for _ in 0..dimensions {
+                        wkb.extend(f64::NAN.to_le_bytes());
+                    }
+                } else {
+                    self.write_points(points.start..points.start + 1, wkb);
+                }
+            }
+            2 => {
+                let points = match self.shape_figures(shape)?.next() {
+                    Some(figure) => self.figure_points(figure)?,
+                    None => 0..0,
+                };
+                wkb.extend((points.len() as u32).to_le_bytes());
+                self.write_points(points, wkb);
+            }
+            3 => {
+                let figures = self.shape_figures(shape)?;
+                wkb.extend((figures.len() as u32).to_le_bytes());
+                for figure in figures {
+                    let points = self.figure_points(figure)?;
+                    wkb.extend((points.len() as u32).to_le_bytes());
+                    self.write_points(points, wkb);
+                }
+            }
+            _ => {
+                let children: Vec<usize> = (shape + 1..self.shapes.len())
+                    .filter(|child| self.shapes[*child].parent == shape as i32)
+                    .collect();
+                wkb.extend((children.len() as u32).to_le_bytes());
+                for child in children {
+                    self.write_shape(child, wkb)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(srid: i32, x: f64, y: f64) -> Vec<u8> {
+        let mut bytes = srid.to_le_bytes().to_vec();
+        bytes.extend([1, 0x0c]);
+        bytes.extend(x.to_le_bytes());
+        bytes.extend(y.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn geography_point_is_written_as_longitude_latitude() {
+        let geometry = clr_to_wkb(&point(4326, 47.65, -122.35), true).unwrap();
+
+        let mut wkb = vec![1, 1, 0, 0, 0];
+        wkb.extend((-122.35f64).to_le_bytes());
+        wkb.extend(47.65f64.to_le_bytes());
+
+        assert_eq!(geometry.srid, 4326);
+        assert_eq!(geometry.geometry_type, "Point");
+        assert_eq!(geometry.wkb, wkb);
+    }
+
+    #[test]
+    fn truncated_value_is_an_error() {
+        let bytes = point(4326, 1.0, 2.0);
+
+        for size in 0..bytes.len() {
+            assert!(clr_to_wkb(&bytes[..size], true).is_err());
+        }
+    }
+
+    #[test]
+    fn oversized_counts_are_an_error() {
+        // pontos, figuras e formas maiores que o valor
+        assert!(clr_to_wkb(&[0, 0, 0, 0, 1, 0, 0xff, 0xff, 0xff, 0xff], false).is_err());
+
+        let mut figures = vec![0, 0, 0, 0, 1, 0, 0, 0, 0, 0];
+        figures.extend([0xff, 0xff, 0xff, 0xff]);
+        assert!(clr_to_wkb(&figures, false).is_err());
+
+        let mut shapes = vec![0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        shapes.extend([0xff, 0xff, 0xff, 0xff]);
+        assert!(clr_to_wkb(&shapes, false).is_err());
+    }
+}
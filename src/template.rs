@@ -0,0 +1,179 @@
+use anyhow::{Context, Ok, bail};
+use chrono::{Datelike, Days, Months, NaiveDate};
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Write;
+
+pub fn render_template(
+    sql: &str,
+    variables: &HashMap<String, String>,
+    today: NaiveDate,
+) -> anyhow::Result<String> {
+    //! Substitui as expressões `{{ ... }}` do texto da consulta.
+    //! Aceita datas (`{{today}}`, `{{today - 1d}}`, `{{month_start}}`),
+    //! variáveis de ambiente (`{{env.NOME}}`) e variáveis do usuário (`{{nome}}`).
+    //! Datas aceitam um formato do chrono após `|`, como `{{today | %Y%m%d}}`.
+    //! Chaves que não formam uma expressão, como um JSON em uma string,
+    //! e `{{` sem fechamento são mantidas no texto.
+
+    let mut rendered = String::with_capacity(sql.len());
+    let mut rest = sql;
+
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+
+        let Some(end) = rest[start..].find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+
+        let expression = &rest[start + 2..start + end];
+        if !is_expression(expression) {
+            rendered.push_str("{{");
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        rendered.push_str(
+            &render_expression(expression.trim(), variables, today)
+                .with_context(|| format!("Expressão inválida: {{{{{}}}}}", expression))?,
+        );
+
+        rest = &rest[start + end + 2..];
+    }
+    rendered.push_str(rest);
+
+    Ok(rendered)
+}
+
+fn is_expression(expression: &str) -> bool {
+    //! Começa por um nome e não tem aspas, chaves ou `:` antes do formato.
+
+    let name = expression.split('|').next().unwrap_or_default().trim();
+
+    name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && !name.contains(['"', '\'', '{', '}', ':', ','])
+}
+
+fn render_expression(
+    expression: &str,
+    variables: &HashMap<String, String>,
+    today: NaiveDate,
+) -> anyhow::Result<String> {
+    if let Some(name) = expression.strip_prefix("env.") {
+        return env::var(name.trim())
+            .with_context(|| format!("Variável de ambiente {} não definida", name.trim()));
+    }
+
+    if let Some(value) = variables.get(expression) {
+        return Ok(value.clone());
+    }
+
+    let (expression, format) = match expression.split_once('|') {
+        Some((expression, format)) => (expression.trim(), format.trim()),
+        None => (expression, "%Y-%m-%d"),
+    };
+
+    let date = eval_date(expression, today)?;
+
+    let mut rendered = String::new();
+    write!(rendered, "{}", date.format(format))
+        .ok()
+        .with_context(|| format!("Formato de data inválido: {}", format))?;
+
+    Ok(rendered)
+}
+
+fn eval_date(expression: &str, today: NaiveDate) -> anyhow::Result<NaiveDate> {
+    //! Avalia `base [+|- N unidade]...`, com as unidades
+    //! `d` (dias), `w` (semanas), `m` (meses) e `y` (anos).
+
+    let expression = expression.replace('+', " + ").replace('-', " - ");
+    let mut tokens = expression.split_whitespace();
+    let base = tokens.next().unwrap_or_default();
+
+    let mut date = match base {
+        "today" => today,
+        "yesterday" => today - Days::new(1),
+        "month_start" => today.with_day(1).unwrap_or(today),
+        "month_end" => today.with_day(1).unwrap_or(today) + Months::new(1) - Days::new(1),
+        "year_start" => NaiveDate::from_ymd_opt(today.year(), 1, 1).unwrap_or(today),
+        "year_end" => NaiveDate::from_ymd_opt(today.year(), 12, 31).unwrap_or(today),
+        _ => bail!("Variável {} não definida", base),
+    };
+
+    while let Some(operator) = tokens.next() {
+        let offset = tokens
+            .next()
+            .with_context(|| format!("Deslocamento ausente após {}", operator))?;
+
+        let (unit_start, _) = offset
+            .char_indices()
+            .last()
+            .with_context(|| format!("Deslocamento inválido: {}", offset))?;
+        let (amount, unit) = offset.split_at(unit_start);
+        let amount: u32 = amount
+            .parse()
+            .with_context(|| format!("Deslocamento inválido: {}", offset))?;
+
+        let days = match unit {
+            "w" => amount.checked_mul(7),
+            _ => Some(amount),
+        };
+        let months = match unit {
+            "y" => amount.checked_mul(12),
+            _ => Some(amount),
+        };
+
+        date = match (operator, unit) {
+            ("+", "d" | "w") => days.and_then(|days| date.checked_add_days(Days::new(days.into()))),
+            ("-", "d" | "w") => days.and_then(|days| date.checked_sub_days(Days::new(days.into()))),
+            ("+", "m" | "y") => {
+                months.and_then(|months| date.checked_add_months(Months::new(months)))
+            }
+            ("-", "m" | "y") => {
+                months.and_then(|months| date.checked_sub_months(Months::new(months)))
+            }
+            _ => bail!("Deslocamento inválido: {} {}", operator, offset),
+        }
+        .with_context(|| format!("Data fora do intervalo: {} {} {}", date, operator, offset))?;
+    }
+
+    Ok(date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(sql: &str) -> anyhow::Result<String> {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 15).unwrap();
+        let variables = HashMap::from([("loja".to_string(), "10".to_string())]);
+
+        render_template(sql, &variables, today)
+    }
+
+    #[test]
+    fn dates_and_variables() {
+        assert_eq!(
+            render("{{today - 1d}} {{month_start - 1m}} {{year_end | %Y%m%d}} {{loja}}").unwrap(),
+            "2024-03-14 2024-02-01 20241231 10"
+        );
+    }
+
+    #[test]
+    fn braces_that_are_not_expressions_are_kept() {
+        let sql = r#"SELECT '{{"a": {"b": 1}}}' AS json, '{{' AS aberto"#;
+
+        assert_eq!(render(sql).unwrap(), sql);
+    }
+
+    #[test]
+    fn invalid_offsets_are_errors() {
+        assert!(render("{{today - 1é}}").is_err());
+        assert!(render("{{today + 999999999d}}").is_err());
+        assert!(render("{{today + 4294967295y}}").is_err());
+        assert!(render("{{today - 4294967295w}}").is_err());
+        assert!(render("{{tody}}").is_err());
+    }
+}